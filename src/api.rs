@@ -0,0 +1,469 @@
+use std::collections::BTreeMap;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Serialize;
+
+use crate::dungeon_state_machine::{AbortReason, DungeonRun, DungeonState, FloorSplit, RunStatics};
+use crate::engines::{InnerStatics, RewardIncome};
+use crate::rates::Rates;
+use crate::replay::Transition;
+use crate::server::{
+    best_run, counter_statics, dungeon_reward_statics, dungeon_statics, floor_statics,
+    gacha_statics, lab_statics, range_rates, rare_feed_statics, replay_session, run_history,
+    session_statics, session_summaries, time_range, total_income, travel_statics, GachaQuery,
+    RunQuery,
+};
+use crate::session::SESSION_COUNTERS;
+use crate::setting::Setting;
+use crate::statics_address::StaticsAddress;
+use crate::store::{ClearRate, LapRecord};
+use crate::time_range::RangeQuery;
+use crate::Context;
+
+/// HTMLのページと同じ統計をJSONで返す
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/v1")
+            .route("/dungeon_reward", web::get().to(dungeon_reward))
+            .route("/dungeon", web::get().to(dungeon))
+            .route("/floor", web::get().to(floor))
+            .route("/sessions", web::get().to(sessions))
+            .route("/session", web::get().to(session))
+            .route("/runs", web::get().to(runs))
+            .route("/rare_feed", web::get().to(rare_feed))
+            .route("/gacha_stats", web::get().to(gacha_stats))
+            .route("/travel", web::get().to(travel))
+            .route("/lab_ledger", web::get().to(lab_ledger))
+            .route("/replay", web::get().to(replay))
+            .route(
+                "/{counter:items|parts|kills|labo|use|gacha|rare_gacha|dungeon_clear|burst|mission|shuttle}",
+                web::get().to(counter),
+            ),
+    );
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct CounterResponse {
+    name: String,
+    statics: InnerStatics,
+    rates: Rates,
+}
+
+#[derive(Serialize)]
+struct DungeonRewardResponse {
+    rewards: InnerStatics,
+    sells: InnerStatics,
+    rates: Rates,
+    sell_rates: Rates,
+}
+
+#[derive(Serialize)]
+struct DungeonResponse {
+    state: DungeonState,
+    dungeon: Option<String>,
+    /// seconds
+    lap_time: Option<i64>,
+    /// seconds
+    best_lap_time: Option<i64>,
+    income: Option<RewardIncome>,
+    income_per_hour: Option<RewardIncome>,
+    splits: Vec<FloorSplit>,
+    /// 自己ベストの周回のフロアごとのタイム
+    best_splits: Vec<FloorSplit>,
+    /// クリアせずに出た理由
+    abort: Option<AbortReason>,
+    statics: Option<DungeonStaticsResponse>,
+}
+
+#[derive(Serialize)]
+struct DungeonStaticsResponse {
+    items: InnerStatics,
+    parts: InnerStatics,
+    item_use: InnerStatics,
+    kills: InnerStatics,
+    rewards: InnerStatics,
+    sells: InnerStatics,
+}
+
+#[derive(Serialize)]
+struct FloorResponse {
+    in_floor: bool,
+    statics: Option<FloorStaticsResponse>,
+}
+
+#[derive(Serialize)]
+struct FloorStaticsResponse {
+    items: InnerStatics,
+    parts: InnerStatics,
+    item_use: InnerStatics,
+    kills: InnerStatics,
+}
+
+#[derive(Serialize)]
+struct SessionResponse {
+    file: String,
+    active: bool,
+    start: Option<String>,
+    end: Option<String>,
+    /// seconds
+    duration: Option<i64>,
+    totals: BTreeMap<String, isize>,
+}
+
+#[derive(Serialize)]
+struct SessionStaticsResponse {
+    session: String,
+    statics: BTreeMap<String, InnerStatics>,
+    /// カウンタごと
+    rates: BTreeMap<String, Rates>,
+}
+
+#[derive(Serialize)]
+struct RunsResponse {
+    income: RewardIncome,
+    income_per_hour: Option<RewardIncome>,
+    records: Vec<LapRecord>,
+    clear_rates: Vec<ClearRate>,
+    runs: Vec<RunResponse>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct RunResponse {
+    dungeon: Option<String>,
+    start: String,
+    clear: Option<String>,
+    exit: String,
+    /// seconds
+    lap_time: Option<i64>,
+    abort: Option<AbortReason>,
+    statics: RunStatics,
+}
+
+impl From<DungeonRun> for RunResponse {
+    fn from(run: DungeonRun) -> Self {
+        let format = |time: chrono::NaiveDateTime| time.format("%Y-%m-%d %H:%M:%S").to_string();
+        Self {
+            dungeon: run.dungeon,
+            start: format(run.start),
+            clear: run.clear.map(format),
+            exit: format(run.exit),
+            lap_time: run.lap_time.map(|lap_time| lap_time.num_seconds()),
+            abort: run.abort,
+            statics: run.statics,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ReplayResponse {
+    transitions: Vec<Transition>,
+    runs: Vec<RunResponse>,
+    state: DungeonState,
+}
+
+/// ブロッキングな処理をスレッドプールで実行してJSONとして返す
+async fn respond<F, T>(context: web::Data<Context>, query: F) -> HttpResponse
+where
+    F: FnOnce(&Context, &Setting) -> T + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    let body = web::block(move || context.config().map(|config| query(&context, &config))).await;
+    match body {
+        Ok(Some(body)) => HttpResponse::Ok().json(body),
+        Ok(None) => HttpResponse::ServiceUnavailable().json(ErrorResponse {
+            error: "Settings.toml is not loaded".to_string(),
+        }),
+        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: error.to_string(),
+        }),
+    }
+}
+
+/// 見つからなければ404を返す
+async fn respond_found<F, T>(context: web::Data<Context>, query: F) -> HttpResponse
+where
+    F: FnOnce(&Context, &Setting) -> Option<T> + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    let body = web::block(move || context.config().map(|config| query(&context, &config))).await;
+    match body {
+        Ok(Some(Some(body))) => HttpResponse::Ok().json(body),
+        Ok(Some(None)) => HttpResponse::NotFound().json(ErrorResponse {
+            error: "not found".to_string(),
+        }),
+        Ok(None) => HttpResponse::ServiceUnavailable().json(ErrorResponse {
+            error: "Settings.toml is not loaded".to_string(),
+        }),
+        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: error.to_string(),
+        }),
+    }
+}
+
+fn bad_request(error: String) -> HttpResponse {
+    HttpResponse::BadRequest().json(ErrorResponse { error })
+}
+
+async fn counter(
+    context: web::Data<Context>,
+    request: HttpRequest,
+    query: web::Query<RangeQuery>,
+) -> HttpResponse {
+    let path = request.path().trim_start_matches("/api/v1");
+    let statics_address = StaticsAddress::from_url(path).unwrap();
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| CounterResponse {
+        name: statics_address.to_string(),
+        statics: counter_statics(context, config, statics_address, &range),
+        rates: range_rates(context, config, statics_address, &range),
+    })
+    .await
+}
+
+async fn dungeon_reward(
+    context: web::Data<Context>,
+    query: web::Query<RangeQuery>,
+) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| {
+        let (rewards, sells) = dungeon_reward_statics(context, config, &range);
+        DungeonRewardResponse {
+            rewards,
+            sells,
+            rates: range_rates(context, config, StaticsAddress::DungeonReward, &range),
+            sell_rates: range_rates(context, config, StaticsAddress::DungeonSell, &range),
+        }
+    })
+    .await
+}
+
+async fn dungeon(context: web::Data<Context>) -> HttpResponse {
+    respond(context, |context, config| {
+        let (state, statics) = dungeon_statics(context, config);
+        let lap_time = statics
+            .as_ref()
+            .and_then(|statics| statics.lap_time)
+            .map(|lap_time| lap_time.num_seconds());
+        let best_run = statics
+            .as_ref()
+            .and_then(|statics| best_run(context, statics));
+        let best_splits = best_run
+            .as_ref()
+            .map(|best| best.statics.splits.clone())
+            .unwrap_or_default();
+        let best_lap_time = best_run
+            .and_then(|best| best.lap_time)
+            .map(|best_lap| best_lap.num_seconds());
+        let dungeon = statics.as_ref().and_then(|statics| statics.dungeon.clone());
+        let abort = statics.as_ref().and_then(|statics| statics.abort);
+        let income = statics.as_ref().map(|statics| statics.income());
+        let income_per_hour = statics.as_ref().and_then(|statics| {
+            statics
+                .lap_time
+                .and_then(|lap_time| statics.income().per_hour(lap_time.num_seconds()))
+        });
+        let splits = statics
+            .as_ref()
+            .map(|statics| statics.splits.clone())
+            .unwrap_or_default();
+        let statics = statics.map(|statics| {
+            let mut iter = statics.statics.into_iter();
+            let items = iter.next().unwrap_or_default();
+            let item_use = iter.next().unwrap_or_default();
+            let parts = iter.next().unwrap_or_default();
+            let kills = iter.next().unwrap_or_default();
+            DungeonStaticsResponse {
+                items,
+                parts,
+                item_use,
+                kills,
+                rewards: statics.rewards,
+                sells: statics.sells,
+            }
+        });
+        DungeonResponse {
+            state,
+            dungeon,
+            lap_time,
+            best_lap_time,
+            income,
+            income_per_hour,
+            splits,
+            best_splits,
+            abort,
+            statics,
+        }
+    })
+    .await
+}
+
+async fn floor(context: web::Data<Context>) -> HttpResponse {
+    respond(context, |context, config| {
+        let statics = floor_statics(context, config).map(|statics| {
+            let mut iter = statics.into_iter();
+            FloorStaticsResponse {
+                items: iter.next().unwrap_or_default(),
+                parts: iter.next().unwrap_or_default(),
+                item_use: iter.next().unwrap_or_default(),
+                kills: iter.next().unwrap_or_default(),
+            }
+        });
+        FloorResponse {
+            in_floor: statics.is_some(),
+            statics,
+        }
+    })
+    .await
+}
+
+async fn sessions(context: web::Data<Context>) -> HttpResponse {
+    respond(context, |context, config| {
+        let format = |time: Option<chrono::NaiveDateTime>| {
+            time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        };
+        session_summaries(context, config)
+            .into_iter()
+            .map(|summary| SessionResponse {
+                start: format(summary.start),
+                end: format(summary.end),
+                duration: summary.duration().map(|duration| duration.num_seconds()),
+                totals: SESSION_COUNTERS
+                    .iter()
+                    .map(|statics_address| statics_address.to_string())
+                    .zip(summary.totals)
+                    .collect(),
+                file: summary.file,
+                active: summary.active,
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+}
+
+async fn session(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) if range.session.is_some() => range,
+        Ok(_) => return bad_request("session is required".to_string()),
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| {
+        let statics = session_statics(context, config, &range);
+        SessionStaticsResponse {
+            session: range.session.clone().unwrap_or_default(),
+            rates: statics
+                .iter()
+                .map(|(statics_address, _)| {
+                    (
+                        statics_address.to_string(),
+                        range_rates(context, config, *statics_address, &range),
+                    )
+                })
+                .collect(),
+            statics: statics
+                .into_iter()
+                .map(|(statics_address, statics)| (statics_address.to_string(), statics))
+                .collect(),
+        }
+    })
+    .await
+}
+
+/// サーバー全体のレアガチャの当たり
+async fn rare_feed(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| {
+        rare_feed_statics(context, config, &range)
+    })
+    .await
+}
+
+/// ガチャのまわしと出現率
+async fn gacha_stats(context: web::Data<Context>, query: web::Query<GachaQuery>) -> HttpResponse {
+    let range = match time_range(&query.range) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    let rare = query.rare.is_some();
+    respond(context, move |context, config| {
+        gacha_statics(context, config, &range, rare)
+    })
+    .await
+}
+
+/// レシピごとの合成の成功率と材料の消費
+async fn lab_ledger(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| {
+        lab_statics(context, config, &range)
+    })
+    .await
+}
+
+/// 移動の履歴と場所ごとの訪問回数と滞在時間
+async fn travel(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return bad_request(error),
+    };
+    respond(context, move |context, config| {
+        travel_statics(context, config, &range)
+    })
+    .await
+}
+
+async fn runs(context: web::Data<Context>, query: web::Query<RunQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    let history = web::block(move || run_history(&context, &query)).await;
+    match history {
+        Ok(Ok((records, clear_rates, runs))) => {
+            let (income, income_per_hour) = total_income(&runs);
+            HttpResponse::Ok().json(RunsResponse {
+                income,
+                income_per_hour,
+                records,
+                clear_rates,
+                runs: runs.into_iter().map(RunResponse::from).collect(),
+            })
+        }
+        Ok(Err(error)) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: error.to_string(),
+        }),
+        Err(error) => HttpResponse::InternalServerError().json(ErrorResponse {
+            error: error.to_string(),
+        }),
+    }
+}
+
+/// ?session=ログファイル名 のログを最初から状態機械に流し直す
+async fn replay(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) if range.session.is_some() => range,
+        Ok(_) => return bad_request("session is required".to_string()),
+        Err(error) => return bad_request(error),
+    };
+    respond_found(context, move |context, config| {
+        replay_session(context, config, &range).map(|replay| ReplayResponse {
+            transitions: replay.transitions,
+            runs: replay.runs.into_iter().map(RunResponse::from).collect(),
+            state: replay.state,
+        })
+    })
+    .await
+}
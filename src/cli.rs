@@ -0,0 +1,365 @@
+use serde::Serialize;
+
+use crate::api::RunResponse;
+use crate::engines::InnerStatics;
+use crate::ingest;
+use crate::rates::Rates;
+use crate::server::{
+    counter_statics, dungeon_reward_statics, range_rates, run_history, time_range, RunQuery,
+};
+use crate::setting::Setting;
+use crate::statics_address::StaticsAddress;
+use crate::time_range::RangeQuery;
+use crate::utils::{format_seconds, format_time, hashmap_to_vec, sort, SortTarget};
+use crate::Context;
+
+pub const USAGE: &str = "\
+usage:
+  c21_counter_rs [serve] [--no-browser]
+  c21_counter_rs stats <counter> [--from DATE] [--to DATE] [--today] [--last-7-days]
+                       [--session FILE] [--format table|json|csv]
+  c21_counter_rs runs [--dungeon NAME] [--sort start|lap_time|dungeon] [--desc]
+                      [--format table|json|csv]
+  c21_counter_rs --replay <FILE>
+
+counter: items parts use kills labo gacha rare_gacha dungeon_clear burst mission shuttle
+         dungeon_reward dungeon_sell";
+
+/// 出力の形式
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+/// 起動時の引数で選ぶ動作
+#[derive(Debug)]
+pub enum Command {
+    /// サーバーを起動する
+    Serve {
+        no_browser: bool,
+    },
+    /// カウンタの統計を出力する
+    Stats {
+        counter: String,
+        range: RangeQuery,
+        format: Format,
+    },
+    /// 周回履歴を出力する
+    Runs {
+        query: RunQuery,
+        format: Format,
+    },
+    /// 過去のログを状態機械に流す
+    Replay {
+        path: String,
+    },
+    Help,
+}
+
+/// プログラム名を除いた引数を読む
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (command, rest) = match args.split_first() {
+        Some((first, rest)) if !first.starts_with("--") => (first.as_str(), rest),
+        _ => ("serve", args),
+    };
+    let mut rest = rest.iter();
+    match command {
+        "serve" => {
+            let mut no_browser = false;
+            while let Some(option) = rest.next() {
+                match option.as_str() {
+                    "--no-browser" => no_browser = true,
+                    "--replay" => {
+                        return Ok(Command::Replay {
+                            path: value(&mut rest, "--replay")?,
+                        })
+                    }
+                    "--help" | "-h" => return Ok(Command::Help),
+                    _ => return Err(format!("unknown option {}", option)),
+                }
+            }
+            Ok(Command::Serve { no_browser })
+        }
+        "stats" => {
+            let counter = value(&mut rest, "stats")?;
+            let mut range = RangeQuery::default();
+            let mut format = Format::Table;
+            while let Some(option) = rest.next() {
+                match option.as_str() {
+                    "--from" => range.from = Some(value(&mut rest, "--from")?),
+                    "--to" => range.to = Some(value(&mut rest, "--to")?),
+                    "--today" => range.today = Some(String::new()),
+                    "--last-7-days" => range.last_7_days = Some(String::new()),
+                    "--session" => range.session = Some(value(&mut rest, "--session")?),
+                    "--format" => format = parse_format(&value(&mut rest, "--format")?)?,
+                    _ => return Err(format!("unknown option {}", option)),
+                }
+            }
+            Ok(Command::Stats {
+                counter,
+                range,
+                format,
+            })
+        }
+        "runs" => {
+            let mut query = RunQuery::default();
+            let mut format = Format::Table;
+            while let Some(option) = rest.next() {
+                match option.as_str() {
+                    "--dungeon" => query.dungeon = Some(value(&mut rest, "--dungeon")?),
+                    "--sort" => query.sort = Some(value(&mut rest, "--sort")?),
+                    "--desc" => query.desc = Some(String::new()),
+                    "--format" => format = parse_format(&value(&mut rest, "--format")?)?,
+                    _ => return Err(format!("unknown option {}", option)),
+                }
+            }
+            Ok(Command::Runs { query, format })
+        }
+        "help" => Ok(Command::Help),
+        _ => Err(format!("unknown command {}", command)),
+    }
+}
+
+/// オプションの値
+fn value(rest: &mut std::slice::Iter<String>, option: &str) -> Result<String, String> {
+    rest.next()
+        .cloned()
+        .ok_or_else(|| format!("{} requires a value", option))
+}
+
+fn parse_format(text: &str) -> Result<Format, String> {
+    match text {
+        "table" => Ok(Format::Table),
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Csv),
+        _ => Err(format!("unknown format {}", text)),
+    }
+}
+
+/// 統計の1行
+#[derive(Serialize)]
+struct StaticsRow {
+    name: String,
+    qty: isize,
+    per_hour: Option<f64>,
+    per_run: Option<f64>,
+}
+
+/// カウンタの統計をキャッシュとデータベースから集計する
+pub fn stats(
+    context: &Context,
+    config: &Setting,
+    counter: &str,
+    range: &RangeQuery,
+    format: Format,
+) -> Result<String, String> {
+    let range = time_range(range)?;
+    let (statics_address, statics): (StaticsAddress, InnerStatics) = match counter {
+        "dungeon_reward" => (
+            StaticsAddress::DungeonReward,
+            dungeon_reward_statics(context, config, &range).0,
+        ),
+        "dungeon_sell" => (
+            StaticsAddress::DungeonSell,
+            dungeon_reward_statics(context, config, &range).1,
+        ),
+        _ => {
+            let statics_address = StaticsAddress::from_url(&format!("/{}", counter))
+                .ok_or_else(|| format!("unknown counter {}", counter))?;
+            (
+                statics_address,
+                counter_statics(context, config, statics_address, &range),
+            )
+        }
+    };
+    let rates: Rates = range_rates(context, config, statics_address, &range);
+    let mut vector = hashmap_to_vec(&statics);
+    sort(&mut vector, SortTarget::NAME, false);
+    let rows: Vec<StaticsRow> = vector
+        .into_iter()
+        .map(|(name, qty)| StaticsRow {
+            per_hour: rates.per_hour(qty),
+            per_run: rates.per_run(&name),
+            name,
+            qty,
+        })
+        .collect();
+    if format == Format::Json {
+        return serde_json::to_string_pretty(&rows).map_err(|error| error.to_string());
+    }
+    let rate = |rate: Option<f64>| rate.map(|rate| format!("{:.1}", rate)).unwrap_or_default();
+    let table = rows
+        .iter()
+        .map(|row| {
+            vec![
+                row.name.clone(),
+                row.qty.to_string(),
+                rate(row.per_hour),
+                rate(row.per_run),
+            ]
+        })
+        .collect();
+    Ok(render(
+        format,
+        &["name", "qty", "per_hour", "per_run"],
+        table,
+    ))
+}
+
+/// 周回履歴をデータベースから読む
+pub fn runs(
+    context: &Context,
+    config: &Setting,
+    query: &RunQuery,
+    format: Format,
+) -> Result<String, String> {
+    //serveを起動したことがなくても,書き込みの終わったログから周回を記録しておく
+    let files = context.chat_files(config);
+    ingest::ingest_files(context, &files.finished, |_| {});
+    let (_, _, runs) = run_history(context, query).map_err(|error| error.to_string())?;
+    if format == Format::Json {
+        let runs: Vec<RunResponse> = runs.into_iter().map(RunResponse::from).collect();
+        return serde_json::to_string_pretty(&runs).map_err(|error| error.to_string());
+    }
+    let table = runs
+        .iter()
+        .map(|run| {
+            vec![
+                run.dungeon.clone().unwrap_or_else(|| "不明".to_string()),
+                format_time(Some(run.start)),
+                format_time(run.clear),
+                format_time(Some(run.exit)),
+                run.lap_time
+                    .map(|lap_time| format_seconds(lap_time.num_seconds()))
+                    .unwrap_or_default(),
+                run.abort
+                    .map(|abort| abort.as_str().to_string())
+                    .unwrap_or_default(),
+                run.statics.income.exp.to_string(),
+                run.statics.income.guild_point.to_string(),
+                run.statics.income.dollar.to_string(),
+            ]
+        })
+        .collect();
+    Ok(render(
+        format,
+        &[
+            "dungeon",
+            "start",
+            "clear",
+            "exit",
+            "lap_time",
+            "abort",
+            "exp",
+            "guild_point",
+            "dollar",
+        ],
+        table,
+    ))
+}
+
+/// 表かcsvにする
+fn render(format: Format, header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let header: Vec<String> = header.iter().map(|name| name.to_string()).collect();
+    let rows: Vec<Vec<String>> = std::iter::once(header).chain(rows).collect();
+    if format == Format::Csv {
+        return rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| csv_escape(cell))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+    let mut widths = vec![0; rows[0].len()];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(text_width(cell));
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let line: String = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{}{}  ", cell, " ".repeat(width - text_width(cell))))
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn csv_escape(cell: &str) -> String {
+    if cell.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+/// 全角の文字は2桁として数える
+fn text_width(text: &str) -> usize {
+    text.chars()
+        .map(|char| if (char as u32) < 0x1100 { 1 } else { 2 })
+        .sum()
+}
+
+#[cfg(test)]
+mod cli_test {
+    use crate::cli::{csv_escape, parse, render, Command, Format};
+
+    #[test]
+    fn test_parse() {
+        let args = |text: &str| {
+            text.split_whitespace()
+                .map(|arg| arg.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert!(matches!(
+            parse(&args("")),
+            Ok(Command::Serve { no_browser: false })
+        ));
+        assert!(matches!(
+            parse(&args("serve --no-browser")),
+            Ok(Command::Serve { no_browser: true })
+        ));
+        assert!(matches!(
+            parse(&args("--replay log.txt")),
+            Ok(Command::Replay { ref path }) if path == "log.txt"
+        ));
+        match parse(&args("stats items --from 2021-01-01 --format csv")) {
+            Ok(Command::Stats {
+                counter,
+                range,
+                format,
+            }) => {
+                assert_eq!(counter, "items");
+                assert_eq!(range.from.as_deref(), Some("2021-01-01"));
+                assert_eq!(format, Format::Csv);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(parse(&args("stats items --format xml")).is_err());
+        assert!(parse(&args("runs --dungeon")).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let rows = vec![vec!["ソード".to_string(), "1".to_string()]];
+        assert_eq!(
+            render(Format::Table, &["name", "qty"], rows.clone()),
+            "name    qty\nソード  1"
+        );
+        assert_eq!(
+            render(Format::Csv, &["name", "qty"], rows),
+            "name,qty\nソード,1"
+        );
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+    }
+}
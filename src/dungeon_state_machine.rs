@@ -18,28 +18,49 @@ sell    |---------------------- |
 | ----                          |
 | summary_of_sell               |
 ---------------------------------
+
+     dungeon
+        ↓ escape_gate|jump|disconnect|inactivity
+     aborted
+        ↓ floor_gate
+     dungeon
 */
 use crate::engines::{
     engine_get_part, engine_item_get, engine_item_use, engine_kill_self, engine_reward_dungeon,
-    search_dungeon_clear_first, search_floor_first, search_reward_first, search_reward_sell_first,
-    InnerStatics,
+    engine_reward_income, gate_destination, jump_destination, search_dungeon_clear_first,
+    search_floor_first, search_reward_first, search_reward_sell_first, GateType, InnerStatics,
+    RewardIncome,
 };
-use chrono::{Local, NaiveDateTime};
+use crate::log_event::{LogEvent, TimedEvent};
+use crate::rules::{self, Rules};
+use crate::statics_address::StaticsAddress;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
 use std::ops::Range;
 use std::option::Option::Some;
+use std::sync::Arc;
 #[derive(Debug)]
 pub struct DungeonStateMachine {
     state: DungeonState,
-    texts: Vec<String>,
-    time_stamps: Vec<NaiveDateTime>,
+    events: Vec<TimedEvent>,
     from: usize,
     current_line: usize,
     clear_time: Option<chrono::NaiveDateTime>,
     start_time: Option<chrono::NaiveDateTime>,
+    //中断した時刻と理由
+    abort: Option<(NaiveDateTime, AbortReason)>,
     dungeon_range: Option<Range<usize>>,
+    //まだ保存していない終わった周回
+    finished_runs: Vec<DungeonRun>,
+    //フロアゲートで続けて入ったときの前の周回のダンジョン
+    gate_dungeon: Option<String>,
+    //作ったときのルール
+    rules: Arc<Rules>,
     emit: bool,
     sync: bool,
+    //過去のログを流すときはログの時刻だけを使う
+    replay: bool,
 }
 #[derive(Debug)]
 pub struct DungeonOutPut {
@@ -47,23 +68,123 @@ pub struct DungeonOutPut {
     pub sells: InnerStatics,
     pub statics: Vec<InnerStatics>,
     pub lap_time: Option<chrono::Duration>,
-    reward_exp: usize,
-    reward_guild_pint: usize,
-    reward_dollar: usize,
+    pub dungeon: Option<String>,
+    pub splits: Vec<FloorSplit>,
+    pub abort: Option<AbortReason>,
+    pub reward_exp: isize,
+    pub reward_guild_pint: isize,
+    pub reward_dollar: isize,
+}
+/// 1周分の統計
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStatics {
+    pub items: InnerStatics,
+    pub parts: InnerStatics,
+    pub item_use: InnerStatics,
+    pub kills: InnerStatics,
+    pub rewards: InnerStatics,
+    pub sells: InnerStatics,
+    #[serde(default)]
+    pub splits: Vec<FloorSplit>,
+    #[serde(default)]
+    pub income: RewardIncome,
+}
+/// フロアゲートの起動から次の起動までのフロア1つ分の区間
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FloorSplit {
+    /// 秒
+    pub duration: i64,
+    pub items: isize,
+    pub parts: isize,
+    pub kills: isize,
+}
+/// 終わったダンジョン1周分の記録
+#[derive(Debug, Clone)]
+pub struct DungeonRun {
+    pub dungeon: Option<String>,
+    pub start: NaiveDateTime,
+    pub clear: Option<NaiveDateTime>,
+    pub exit: NaiveDateTime,
+    pub lap_time: Option<chrono::Duration>,
+    /// クリアせずに終わった理由
+    pub abort: Option<AbortReason>,
+    pub statics: RunStatics,
+}
+/// ダンジョンをクリアせずに出た理由
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum AbortReason {
+    EscapeGate,
+    Jump,
+    /// ログファイルが切り替わった
+    Disconnect,
+    /// 長い間ログが書かれなかった
+    Inactive,
+}
+impl AbortReason {
+    /// データベースに保存する名前
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AbortReason::EscapeGate => "escape_gate",
+            AbortReason::Jump => "jump",
+            AbortReason::Disconnect => "disconnect",
+            AbortReason::Inactive => "inactive",
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            AbortReason::EscapeGate => "エスケープゲート",
+            AbortReason::Jump => "ジャンプ",
+            AbortReason::Disconnect => "切断",
+            AbortReason::Inactive => "放置",
+        }
+    }
+}
+/// as_strの逆
+impl std::str::FromStr for AbortReason {
+    type Err = String;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text {
+            "escape_gate" => Ok(AbortReason::EscapeGate),
+            "jump" => Ok(AbortReason::Jump),
+            "disconnect" => Ok(AbortReason::Disconnect),
+            "inactive" => Ok(AbortReason::Inactive),
+            _ => Err(format!("unknown abort reason: {}", text)),
+        }
+    }
+}
+impl DungeonOutPut {
+    pub fn income(&self) -> RewardIncome {
+        RewardIncome {
+            exp: self.reward_exp,
+            guild_point: self.reward_guild_pint,
+            dollar: self.reward_dollar,
+        }
+    }
 }
 impl DungeonStateMachine {
-    pub fn init(texts: Vec<String>, time_stamps: Vec<NaiveDateTime>, from: usize) -> Self {
+    pub fn init(events: Vec<TimedEvent>, from: usize) -> Self {
         Self {
             state: DungeonState::OutOfDungeon,
-            texts,
-            time_stamps,
+            events,
             from,
             current_line: from,
             clear_time: None,
             start_time: None,
+            abort: None,
             dungeon_range: None,
+            finished_runs: Vec::new(),
+            gate_dungeon: None,
+            rules: rules::current(),
             emit: false,
             sync: false,
+            replay: false,
+        }
+    }
+    /// 過去のログを最初から流し直す
+    pub fn replay(events: Vec<TimedEvent>) -> Self {
+        Self {
+            replay: true,
+            ..Self::init(events, 0)
         }
     }
     pub fn statics(&mut self) -> Option<DungeonOutPut> {
@@ -73,12 +194,20 @@ impl DungeonStateMachine {
             self.from..self.current_line
         };
 
-        let texts = &self.texts[range];
-        let items = engine_item_get(texts, 0);
-        let item_use = engine_item_use(texts, 0);
-        let parts = engine_get_part(texts, 0);
-        let kill = engine_kill_self(texts, 0);
-        let rewards = engine_reward_dungeon(texts, 0);
+        let events = &self.events[range];
+        let items = engine_item_get(events, 0);
+        let item_use = engine_item_use(events, 0);
+        let parts = engine_get_part(events, 0);
+        let kill = engine_kill_self(events, 0);
+        let rewards = engine_reward_dungeon(events, 0);
+        let income = engine_reward_income(events, 0);
+        let dungeon = identify_dungeon(events).or_else(|| self.gate_dungeon.clone());
+        let end = match self.state {
+            DungeonState::Dungeon => self.get_current_time(),
+            DungeonState::Aborted => self.abort.unwrap().0,
+            _ => self.clear_time.unwrap_or_else(|| self.get_current_time()),
+        };
+        let splits = floor_splits(events, end);
 
         let lap_time = match self.state {
             DungeonState::OutOfDungeon => None,
@@ -87,6 +216,8 @@ impl DungeonStateMachine {
 
                 Some(time - self.start_time.unwrap())
             }
+            //中断するまでの時間で止める
+            DungeonState::Aborted => Some(end - self.start_time.unwrap()),
             _ => Some(self.clear_time.unwrap() - self.start_time.unwrap()),
         };
         Some(DungeonOutPut {
@@ -94,31 +225,131 @@ impl DungeonStateMachine {
             sells: rewards.1,
             statics: vec![items, item_use, parts, kill],
             lap_time,
-            reward_exp: 0,
-            reward_guild_pint: 0,
-            reward_dollar: 0,
+            dungeon,
+            splits,
+            abort: self.abort.map(|(_, reason)| reason),
+            reward_exp: income.exp,
+            reward_guild_pint: income.guild_point,
+            reward_dollar: income.dollar,
         })
     }
     fn get_current_time(&self) -> NaiveDateTime {
         if self.sync {
             chrono::Local::now().naive_local()
         } else {
-            self.time_stamps[self.current_line]
+            self.current_event()
+                .map(|event| event.time)
+                .unwrap_or_else(|| chrono::Local::now().naive_local())
+        }
+    }
+    /// 周回が終わった
+    /// 終わった周回のダンジョンを返す
+    fn finish_run(&mut self, range: Range<usize>, exit: NaiveDateTime) -> Option<String> {
+        let gate_dungeon = self.gate_dungeon.take();
+        let events = &self.events[range.clone()];
+        let dungeon = identify_dungeon(events).or(gate_dungeon);
+        let (rewards, sells) = engine_reward_dungeon(events, 0);
+        let abort = self.abort.map(|(_, reason)| reason);
+        let statics = RunStatics {
+            items: engine_item_get(events, 0),
+            parts: engine_get_part(events, 0),
+            item_use: engine_item_use(events, 0),
+            kills: engine_kill_self(events, 0),
+            rewards,
+            sells,
+            splits: floor_splits(events, self.clear_time.unwrap_or(exit)),
+            income: engine_reward_income(events, 0),
+        };
+        if let Some(start) = self.start_time {
+            self.finished_runs.push(DungeonRun {
+                dungeon: dungeon.clone(),
+                start,
+                clear: self.clear_time,
+                exit,
+                lap_time: self.clear_time.map(|clear| clear - start),
+                abort,
+                statics,
+            });
+        }
+        self.dungeon_range.replace(range);
+        dungeon
+    }
+    /// クリアする前にダンジョンを出た
+    /// endより前のイベントまでを1周とする
+    fn abort_run(&mut self, end: usize, exit: NaiveDateTime, reason: AbortReason) {
+        self.state = DungeonState::Aborted;
+        self.abort = Some((exit, reason));
+        self.finish_run(self.from..end, exit);
+        self.current_line = end;
+        self.from = end;
+    }
+    /// ダンジョンにいる間に中断につながるイベントを探す
+    /// 長い間ログがなければその前で中断したとみなす
+    fn search_abort(&self, from: usize, to: usize) -> Option<(usize, NaiveDateTime, AbortReason)> {
+        for index in from..to {
+            let event = &self.events[index];
+            if index > self.from {
+                let previous = self.events[index - 1].time;
+                if event.time - previous > self.rules.inactivity_timeout() {
+                    return Some((index, previous, AbortReason::Inactive));
+                }
+            }
+            let reason = match gate_destination(&event.event) {
+                Some((_, GateType::Escape)) => Some(AbortReason::EscapeGate),
+                _ => jump_destination(&event.event).map(|_| AbortReason::Jump),
+            };
+            if let Some(reason) = reason {
+                return Some((index + 1, event.time, reason));
+            }
+        }
+        None
+    }
+    /// ダンジョンにいるなら中断して記録する
+    /// ログファイルが切り替わるときに呼ぶ
+    pub fn abort(&mut self, reason: AbortReason) {
+        if self.state == DungeonState::Dungeon {
+            let exit = self.events.last().map(|event| event.time);
+            if let Some(exit) = exit {
+                self.abort_run(self.events.len(), exit, reason);
+            }
+        }
+    }
+    /// クリアしてから報酬を待つ時間が過ぎていれば周回を終える
+    /// 同期しているならシステムの時刻で,そうでなければ次のログの時刻で判定する
+    /// 終わった時刻は待ち時間の切れたとき
+    fn reward_timed_out(&mut self) -> bool {
+        let timeout = self.clear_time.unwrap() + self.rules.reward_timeout();
+        if self.get_current_time() <= timeout {
+            return false;
         }
+        //待ち時間を過ぎてからのイベントは次の周回を探すのに残す
+        let end = (self.from..self.events.len())
+            .find(|index| self.events[*index].time > timeout)
+            .unwrap_or(self.events.len());
+        self.state = DungeonState::OutOfDungeon;
+        self.finish_run(self.from..end, timeout);
+        self.current_line = end;
+        self.from = end;
+        true
     }
-    pub fn state_change(&mut self) {
+    fn state_change(&mut self) {
         match self.state {
-            DungeonState::OutOfDungeon => {
-                let floor_gate = search_floor_first(&self.texts, self.current_line);
+            DungeonState::OutOfDungeon | DungeonState::Aborted => {
+                let floor_gate = search_floor_first(&self.events, self.current_line);
                 if let Some(floor_gate) = floor_gate {
                     self.from = floor_gate;
                     self.state = DungeonState::Dungeon;
+                    self.gate_dungeon = None;
+                    self.clear_time = None;
+                    self.abort = None;
                     self.current_line = floor_gate;
-                    self.start_time.replace(self.time_stamps[self.current_line]);
+                    self.start_time.replace(self.events[self.current_line].time);
 
-                    let current_time = self.time_stamps[self.current_line];
+                    let current_time = self.events[self.current_line].time;
                     let world_current_time = chrono::Local::now().naive_local();
-                    if (world_current_time - current_time) < chrono::Duration::seconds(6) {
+                    if !self.replay
+                        && (world_current_time - current_time) < self.rules.sync_threshold()
+                    {
                         #[cfg(debug_assertions)]
                         println!("set sync");
                         self.sync = true;
@@ -126,31 +357,40 @@ impl DungeonStateMachine {
                 }
             }
             DungeonState::Dungeon => {
-                let clear = search_dungeon_clear_first(&self.texts, self.current_line);
+                let clear = search_dungeon_clear_first(&self.events, self.current_line);
+                let abort =
+                    self.search_abort(self.current_line, clear.unwrap_or(self.events.len()));
+                if let Some((end, exit, reason)) = abort {
+                    self.abort_run(end, exit, reason);
+                    return;
+                }
                 if let Some(clear) = clear {
                     self.state = DungeonState::Clear;
                     self.current_line = clear;
-                    let current_time = self.time_stamps[self.current_line];
+                    let current_time = self.events[self.current_line].time;
                     self.clear_time.replace(current_time);
                     #[cfg(debug_assertions)]
                     println!("clear time {:?}", self.clear_time)
                 } else {
-                    self.current_line = self.texts.len();
+                    self.current_line = self.events.len();
+                    //追記がないまま時間が経った
+                    if let Some(last) = self.events.last().map(|event| event.time) {
+                        if self.sync
+                            && chrono::Local::now().naive_local() - last
+                                > self.rules.inactivity_timeout()
+                        {
+                            self.abort_run(self.events.len(), last, AbortReason::Inactive);
+                        }
+                    }
                 }
             }
             DungeonState::Clear => {
-                //同期しているならcurrent_time をシステムの時間にセットする
-                let current_time = self.get_current_time();
-                if (current_time - self.clear_time.unwrap()) > chrono::Duration::seconds(120) {
-                    self.state = DungeonState::OutOfDungeon;
-                    self.dungeon_range.replace(self.from..self.current_line);
-                    self.current_line = self.texts.len();
-                    self.from = self.texts.len();
+                if self.reward_timed_out() {
                     return;
                 }
 
-                let reward_start = search_reward_first(&self.texts, self.current_line);
-                while self.state == DungeonState::Clear && (self.current_line < self.texts.len()) {
+                let reward_start = search_reward_first(&self.events, self.current_line);
+                while self.state == DungeonState::Clear && (self.current_line < self.events.len()) {
                     if let Some(reward_start) = reward_start {
                         self.state = DungeonState::Reward;
                         self.current_line = reward_start;
@@ -160,27 +400,20 @@ impl DungeonStateMachine {
                 }
             }
             DungeonState::Reward => {
-                // check time out
-                let current_time = self.get_current_time();
-                if (current_time - self.clear_time.unwrap()) > chrono::Duration::seconds(120) {
-                    self.state = DungeonState::OutOfDungeon;
-                    self.dungeon_range.replace(self.from..self.current_line);
-                    self.current_line = self.texts.len();
-                    self.from = self.texts.len();
+                if self.reward_timed_out() {
                     return;
                 }
-                while self.state == DungeonState::Reward && (self.current_line < self.texts.len()) {
-                    //同期しているならcurrent_time をシステムの時間にセットする
-
-                    let activate_floor_gate = search_floor_first(&self.texts, self.current_line);
-                    let sell_start = search_reward_sell_first(&self.texts, self.current_line);
-                    #[cfg(debug_assertions)]
-                    println!("elapsed time {}", current_time - self.clear_time.unwrap());
+                while self.state == DungeonState::Reward && (self.current_line < self.events.len())
+                {
+                    let activate_floor_gate = search_floor_first(&self.events, self.current_line);
+                    let sell_start = search_reward_sell_first(&self.events, self.current_line);
                     if let Some(floor_gate) = activate_floor_gate {
                         self.state = DungeonState::Dungeon;
-                        self.start_time.replace(self.time_stamps[self.current_line]);
                         self.current_line = floor_gate;
-                        self.dungeon_range.replace(self.from..self.current_line);
+                        let exit = self.events[self.current_line].time;
+                        self.gate_dungeon = self.finish_run(self.from..self.current_line, exit);
+                        self.start_time.replace(self.events[self.current_line].time);
+                        self.clear_time = None;
                         self.from = self.current_line;
                     } else if let Some(sell) = sell_start {
                         self.state = DungeonState::Sell;
@@ -192,11 +425,16 @@ impl DungeonStateMachine {
                 //ログが足りなくなるとここに来る
             }
             DungeonState::Sell => {
-                while self.state == DungeonState::Sell && (self.current_line < self.texts.len()) {
-                    if self.texts[self.current_line].contains("報酬売却計") {
+                while self.state == DungeonState::Sell && (self.current_line < self.events.len()) {
+                    if matches!(
+                        self.events[self.current_line].event,
+                        LogEvent::SellTotal { .. }
+                    ) {
                         self.state = DungeonState::OutOfDungeon;
-                        //save
-                        self.dungeon_range.replace(self.from..self.current_line);
+                        //売却の合計までを1周に含める
+                        let exit = self.events[self.current_line].time;
+                        self.current_line += 1;
+                        self.finish_run(self.from..self.current_line, exit);
                         self.from = self.current_line;
                     } else {
                         self.current_line += 1;
@@ -208,23 +446,124 @@ impl DungeonStateMachine {
     pub fn inspect_state(&self) -> &DungeonState {
         self.state.borrow()
     }
-    pub fn supply_text(&mut self, other: (&[NaiveDateTime], &[String])) {
-        self.texts.extend_from_slice(other.1);
-        self.time_stamps.extend_from_slice(other.0);
+    /// 次に調べるイベント
+    /// ログの末尾まで読み終えていれば最後のイベント
+    pub fn current_event(&self) -> Option<&TimedEvent> {
+        self.events
+            .get(self.current_line)
+            .or_else(|| self.events.last())
     }
-    pub fn get_current_text_len(&self) -> usize {
-        self.texts.len()
+    /// イベントを1つずつ加えて,そのたびに進めなくなるまで状態を進める
+    /// まとめて加えると,後のフロアゲートが先の売却や待ち時間の判定より先に見つかってしまう
+    /// 遷移するたびにon_transitionに前後の状態とそのとき見ていたイベントを渡す
+    pub fn feed<F>(&mut self, events: &[TimedEvent], mut on_transition: F)
+    where
+        F: FnMut(DungeonState, DungeonState, Option<&TimedEvent>),
+    {
+        for event in events {
+            self.events.push(event.clone());
+            self.advance(&mut on_transition);
+        }
+    }
+    /// 状態と位置が変わらなくなるまで進める
+    /// 追記がなくても,同期しているなら時間が経てば進む
+    pub fn advance<F>(&mut self, mut on_transition: F)
+    where
+        F: FnMut(DungeonState, DungeonState, Option<&TimedEvent>),
+    {
+        loop {
+            let (from, line) = (self.state, self.current_line);
+            self.state_change();
+            if from != self.state {
+                on_transition(from, self.state, self.current_event());
+            }
+            if (from, line) == (self.state, self.current_line) {
+                break;
+            }
+        }
     }
     pub fn query_dungeon_range(&mut self) -> Option<Range<usize>> {
         self.dungeon_range.take()
     }
+    /// 前回の呼び出しから終わった周回を取り出す
+    pub fn take_runs(&mut self) -> Vec<DungeonRun> {
+        std::mem::take(&mut self.finished_runs)
+    }
+}
+/// dungeon.tsvにあるクリアやボスのメッセージからダンジョン名を得る
+fn identify_dungeon(events: &[TimedEvent]) -> Option<String> {
+    events.iter().rev().find_map(|event| {
+        let (name, _) = event.event.count(StaticsAddress::DungeonClear)?;
+        //a01,ポルド遺跡
+        let name = name.split_once(',').map_or(name, |(_, name)| name);
+        Some(name.to_string())
+    })
+}
+/// フロアゲートの起動ごとに区切る
+/// 最後のフロアはendまで
+pub fn floor_splits(events: &[TimedEvent], end: NaiveDateTime) -> Vec<FloorSplit> {
+    let gates: Vec<usize> = events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| matches!(event.event, LogEvent::FloorGate { .. }))
+        .map(|(index, _)| index)
+        .collect();
+    let total = |statics: InnerStatics| statics.values().sum();
+    gates
+        .iter()
+        .enumerate()
+        .map(|(number, &gate)| {
+            let next = gates.get(number + 1).copied();
+            let floor = &events[gate..next.unwrap_or(events.len())];
+            let start = events[gate].time;
+            let split_end = next.map_or(end, |next| events[next].time);
+            FloorSplit {
+                duration: (split_end - start).num_seconds().max(0),
+                items: total(engine_item_get(floor, 0)),
+                parts: total(engine_get_part(floor, 0)),
+                kills: total(engine_kill_self(floor, 0)),
+            }
+        })
+        .collect()
 }
 ///ダンジョンの状態
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
 pub enum DungeonState {
     OutOfDungeon,
     Dungeon,
     Clear,
     Reward,
     Sell,
+    /// クリアせずに出た
+    Aborted,
+}
+
+#[cfg(test)]
+mod dungeon_state_machine_test {
+    use chrono::NaiveDate;
+
+    use crate::dungeon_state_machine::floor_splits;
+    use crate::log_event::EventParser;
+
+    #[test]
+    fn test_floor_splits() {
+        let text = "2021-01-01\t00:00:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:00:10\t[INFO]\t[リペアパック] を 2個 取得した！\r\n\
+                    2021-01-01\t00:00:20\t[INFO]\tホネボンを撃破した\r\n\
+                    2021-01-01\t00:01:00\t[INFO]\tBがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:01:30\t[INFO]\t[ソード] を取得した！\r\n";
+        let events = EventParser::new().parse(text);
+        let end = NaiveDate::from_ymd_opt(2021, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 2, 30)
+            .unwrap();
+        let splits = floor_splits(&events, end);
+        assert_eq!(splits.len(), 2);
+        assert_eq!(splits[0].duration, 60);
+        assert_eq!(splits[0].items, 2);
+        assert_eq!(splits[0].kills, 1);
+        assert_eq!(splits[0].parts, 0);
+        assert_eq!(splits[1].duration, 90);
+        assert_eq!(splits[1].parts, 1);
+    }
 }
@@ -1,51 +1,34 @@
-use chrono::NaiveDateTime;
-use regex::{Captures, Regex};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::ops::Add;
 
+use crate::log_event::{LogEvent, TimedEvent};
+use crate::statics_address::StaticsAddress;
+
 pub type InnerStatics = HashMap<String, isize>;
 
-pub fn engine_kill_self(texts: &[String], from: usize) -> InnerStatics {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"\t(?P<name>[^が]+?)を撃破した").unwrap();
-    }
+/// 分類済みのイベントをカウンタごとに数え上げる
+pub fn engine_count(
+    events: &[TimedEvent],
+    from: usize,
+    statics_address: StaticsAddress,
+) -> InnerStatics {
     let mut table = HashMap::new();
-    let last = texts.len();
+    let last = events.len();
     if from > last {
         return table;
     }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(text) {
-            let name = caps.name("name").unwrap().as_str();
-            match table.get_mut(name) {
-                None => {
-                    table.insert(name.to_string(), 1);
-                }
-                Some(value) => {
-                    *value += 1;
-                }
-            }
+    for event in &events[from..last] {
+        if let Some((name, qty)) = event.event.count(statics_address) {
+            add_to_table(&mut table, name, qty);
         }
     }
     table
 }
 
-pub fn engine_gacha(texts: &[String], from: usize) -> InnerStatics {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"\[(?P<name>.+)] が当たりました！").unwrap();
-    }
-    let mut table: HashMap<String, isize> = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(&text) {
-            let name = caps.name("name").unwrap().as_str();
-            add_to_table(&mut table, name, 1);
-        }
-    }
-    table
+pub fn engine_kill_self(events: &[TimedEvent], from: usize) -> InnerStatics {
+    engine_count(events, from, StaticsAddress::Kill)
 }
 
 pub(crate) fn add_to_table<V: Add + Copy + std::ops::Add<Output = V>>(
@@ -76,378 +59,179 @@ impl Add for DungeonRewardElement {
     }
 }
 
-pub fn engine_item_use(texts: &[String], from: usize) -> InnerStatics {
-    //[リペアパック2000] を使用した！
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?P<name>\[.+]) を使用した！").unwrap();
-    }
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(&text) {
-            let name = caps.name("name").unwrap().as_str();
-            add_to_table(&mut table, name, 1);
-        }
-    }
-    table
+pub fn engine_item_use(events: &[TimedEvent], from: usize) -> InnerStatics {
+    engine_count(events, from, StaticsAddress::ItemUse)
 }
 
 //this is not normal format
 //so i use dedicated format
 // (reward,sells)
-pub(crate) fn engine_reward_dungeon(texts: &[String], from: usize) -> (InnerStatics, InnerStatics) {
-    lazy_static! {
-    //	報酬－ ENパック2000 x 1
-    static ref REREWARD:Regex=Regex::new(r"報酬－ (?P<name>.+) x (?P<N>\d+)").unwrap();
-    static ref RESELL:Regex=Regex::new(r"報酬売却－ (?P<name>.+) x (?P<N>\d+)").unwrap();
-    }
-    let mut table_reward = HashMap::new();
-    let mut table_sell = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return (table_reward, table_sell);
-    }
-    for text in &texts[from..last] {
-        match REREWARD.captures(&text) {
-            Some(caps) => {
-                let name = caps.name("name").unwrap().as_str();
-                let num = caps.name("N").unwrap().as_str().parse::<isize>().unwrap();
-                add_to_table(&mut table_reward, name, num);
-            }
-            None => {
-                #[cfg(debug_assertions)]
-                println!("{}", &text)
-            }
-        }
-        match RESELL.captures(&text) {
-            Some(caps) => {
-                let name = caps.name("name").unwrap().as_str();
-                let num = caps.name("N").unwrap().as_str().parse::<isize>().unwrap();
-                add_to_table(&mut table_sell, name, num);
-            }
-            None => {
-                #[cfg(debug_assertions)]
-                println!("{}", &text)
-            }
-        }
+pub(crate) fn engine_reward_dungeon(
+    events: &[TimedEvent],
+    from: usize,
+) -> (InnerStatics, InnerStatics) {
+    (
+        engine_count(events, from, StaticsAddress::DungeonReward),
+        engine_count(events, from, StaticsAddress::DungeonSell),
+    )
+}
+
+/// 報酬と売却で得たEXP,ギルドポイント,ドル
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RewardIncome {
+    pub exp: isize,
+    pub guild_point: isize,
+    pub dollar: isize,
+}
+
+impl RewardIncome {
+    pub fn add(&mut self, other: &RewardIncome) {
+        self.exp += other.exp;
+        self.guild_point += other.guild_point;
+        self.dollar += other.dollar;
+    }
+    /// 1時間あたり
+    pub fn per_hour(&self, seconds: i64) -> Option<RewardIncome> {
+        if seconds <= 0 {
+            return None;
+        }
+        let rate = |value: isize| (value as i64 * 3600 / seconds) as isize;
+        Some(RewardIncome {
+            exp: rate(self.exp),
+            guild_point: rate(self.guild_point),
+            dollar: rate(self.dollar),
+        })
     }
-    (table_reward, table_sell)
 }
 
-pub fn engine_rare(texts: &[String], from: usize) -> InnerStatics {
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"誰かが \[(?P<name>.+)] をガチャセンターで当てました！").unwrap();
+/// 報酬－ EXP x 1200 のような報酬と報酬売却計を合計する
+/// ドルは報酬売却計からだけ得る
+pub fn engine_reward_income(events: &[TimedEvent], from: usize) -> RewardIncome {
+    let mut income = RewardIncome::default();
+    if from > events.len() {
+        return income;
     }
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(&text) {
-            let name = caps.name("name").unwrap().as_str();
-            add_to_table(&mut table, name, 1);
+    for event in &events[from..] {
+        match event.event {
+            LogEvent::DungeonReward { ref name, qty } => match name.as_str() {
+                "EXP" => income.exp += qty,
+                "ギルドポイント" => income.guild_point += qty,
+                _ => {}
+            },
+            LogEvent::SellTotal { amount } => income.dollar += amount,
+            _ => {}
         }
     }
-    table
+    income
 }
 
-pub fn engine_labo(texts: &[String], from: usize) -> InnerStatics {
-    //1個も合成に成功しないなら
-    // 合成に失敗しました
-    //1個でも合成に成功したら
-    // (?P<name>) × \d+ の作成に成功しました。有機的な破片 × 4
-    lazy_static! {
-    static ref RE:Regex = Regex::new(r"(?P<name>.+) × (?P<N>[0-9]+)").unwrap();
-    //先ずはre0でマッチさせてそして新旧判定とHashTableへの登録を行う
-    static ref RE0:Regex = Regex::new(r"(?P<name>.+) の作成に成功しました。").unwrap();
-        }
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE0.captures(text) {
-            let name = caps.name("name").unwrap().as_str();
-            let caps = RE.captures(name);
-            let (k, val) = match caps {
-                None => {
-                    //古いバージョンのログ
-                    (name, 1)
-                }
-                Some(caps) => {
-                    //新しいバージョンのログ
-                    let name = caps.name("name").unwrap().as_str();
-                    let num = caps.name("N").unwrap().as_str().parse::<isize>().unwrap();
-                    (name, num)
-                }
-            };
-            #[cfg(debug_assertions)]
-            println!("{}:{}", k, val);
-            add_to_table(&mut table, k, val);
-        }
-    }
-    table
+pub fn engine_item_get(events: &[TimedEvent], from: usize) -> InnerStatics {
+    engine_count(events, from, StaticsAddress::Item)
 }
 
-pub fn engine_tsv_match(
-    texts: &[String],
-    dictionary: &HashMap<String, String>,
-    from: usize,
-) -> InnerStatics {
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        let mut text = text.clone();
-        text = text.replace("\r", "");
-        text = text.replace("\t", "\\t");
-        text.remove(0);
-        text.remove(0);
-        let name = dictionary.get(&text);
-        if let Some(name) = name {
-            add_to_table(&mut table, name, 1);
-        }
-    }
-    table
+pub fn engine_get_part(events: &[TimedEvent], from: usize) -> InnerStatics {
+    engine_count(events, from, StaticsAddress::Parts)
 }
 
-pub fn engine_item_get(texts: &[String], from: usize) -> InnerStatics {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?P<name>\[.+]) を (?P<N>\d+)個 取得した！").unwrap();
-    }
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(&text) {
-            let name = caps.name("name").unwrap().as_str();
-            let num = caps.name("N").unwrap().as_str().parse::<isize>().unwrap();
-            add_to_table(&mut table, name, num);
-        }
+/// 条件を満たす最初のイベントを探す
+fn search_first<F: Fn(&LogEvent) -> bool>(
+    events: &[TimedEvent],
+    search_from: usize,
+    predicate: F,
+) -> Option<usize> {
+    let last = events.len();
+    if search_from > last {
+        return None;
     }
-    table
+    events[search_from..last]
+        .iter()
+        .position(|event| predicate(&event.event))
+        .map(|offset| search_from + offset)
 }
 
-pub fn engine_get_part(texts: &[String], from: usize) -> InnerStatics {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?P<name>\[.+]) を取得した！").unwrap();
-    }
-    let mut table = HashMap::new();
-    let last = texts.len();
-    if from > last {
-        return table;
-    }
-    for text in &texts[from..last] {
-        if let Some(caps) = RE.captures(&text) {
-            let name = caps.name("name").unwrap().as_str();
-            add_to_table(&mut table, name, 1);
-        }
+/// 条件を満たす最後のイベントを探す
+fn search_last<F: Fn(&LogEvent) -> bool>(
+    events: &[TimedEvent],
+    search_from: usize,
+    predicate: F,
+) -> Option<usize> {
+    let last = events.len();
+    if search_from > last {
+        return None;
     }
-    table
+    events[search_from..last]
+        .iter()
+        .rposition(|event| predicate(&event.event))
+        .map(|offset| search_from + offset)
+}
+
+fn is_floor_gate(event: &LogEvent) -> bool {
+    matches!(event, LogEvent::FloorGate { .. })
 }
 
 ///フロアゲートの起動を探す.(last)
-pub fn search_floor_last(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    let mut floor = None;
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        #[cfg(debug_assertions)]
-        println!("offset {} ", offset);
-        if text.contains("がフロアゲートを起動した！") {
-            floor = Some(search_from + offset);
-        }
-    }
-    floor
+pub fn search_floor_last(events: &[TimedEvent], search_from: usize) -> Option<usize> {
+    search_last(events, search_from, is_floor_gate)
 }
 
 ///フロアゲートの起動を探す.(first)
-pub fn search_floor_first(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?P<name>.+?)がフロアゲートを起動した！").unwrap();
-    }
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if RE.captures(text).is_some() {
-            return Some(offset + search_from);
-        }
-    }
-    None
+pub fn search_floor_first(events: &[TimedEvent], search_from: usize) -> Option<usize> {
+    search_first(events, search_from, is_floor_gate)
 }
-/// ジャンプの使用を探す first
-pub fn search_jump_use(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"[(?P<city>.+?)ジャンプ]を使用した！").unwrap();
-    }
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if RE.captures(text).is_some() {
-            return Some(offset + search_from);
-        }
-    }
-    None
+lazy_static! {
+    //[INFO]\t[ポルドジャンプ]を使用した！
+    static ref RE_JUMP: Regex =
+        Regex::new(r"^\[INFO]\\t\[(?P<city>.+?)ジャンプ(?P<rank>★\d)?]を使用した！").unwrap();
+    static ref RE_GATE: Regex =
+        Regex::new(r"^\[INFO]\\t\[(?P<city>.+?)ゲート(?P<rank>★\d)?]を使用した！").unwrap();
 }
-/// ゲートの使用を探す first
-pub fn search_gate_type(texts: &[String], search_from: usize) -> Option<(usize, GateType)> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"[(?P<city>.+?)ゲート]を使用した！").unwrap();
-    }
-    let last = texts.len();
-    if search_from > last {
-        return None;
+
+/// 行き先の名前 ★付きのものは★まで含める
+fn destination(caps: regex::Captures) -> String {
+    let rank = caps.name("rank").map_or("", |rank| rank.as_str());
+    format!("{}{}", &caps["city"], rank)
+}
+
+/// ジャンプの行き先
+pub fn jump_destination(event: &LogEvent) -> Option<String> {
+    match event {
+        LogEvent::ScriptedMessage { key } => RE_JUMP.captures(key).map(destination),
+        _ => None,
     }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if let Some(caps) = RE.captures(text) {
-            let gate_type = if "エスケープ" == caps.name("city").unwrap().as_str() {
+}
+
+/// ゲートの行き先と種類
+pub fn gate_destination(event: &LogEvent) -> Option<(String, GateType)> {
+    match event {
+        LogEvent::ScriptedMessage { key } => RE_GATE.captures(key).map(|caps| {
+            let gate_type = if "エスケープ" == &caps["city"] {
                 GateType::Escape
             } else {
                 GateType::Any
             };
-            return Some((offset + search_from, gate_type));
-        }
+            (destination(caps), gate_type)
+        }),
+        _ => None,
     }
-    None
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
 pub enum GateType {
     Any,
     Escape,
 }
 ///ダンジョンクリア(first)
-pub fn search_dungeon_clear_first(texts: &[String], search_from: usize) -> Option<usize> {
+pub fn search_dungeon_clear_first(events: &[TimedEvent], search_from: usize) -> Option<usize> {
     //ダンジョン成功報酬
-    let last = texts.len();
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if text.contains(r"ダンジョン成功報酬") {
-            return Some(search_from + offset);
-        }
-    }
-    None
+    search_first(events, search_from, |event| {
+        *event == LogEvent::DungeonClear
+    })
 }
 /// search reward line
-pub fn search_reward_first(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if text.contains(r"報酬") {
-            return Some(search_from + offset);
-        }
-    }
-    None
+pub fn search_reward_first(events: &[TimedEvent], search_from: usize) -> Option<usize> {
+    search_first(events, search_from, LogEvent::is_reward)
 }
 /// search reward sell line
-pub fn search_reward_sell_first(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    if search_from > last {
-        return None;
-    }
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if text.contains(r"報酬売却") {
-            return Some(search_from + offset);
-        }
-    }
-    None
-}
-//ダンジョンクリア(last)
-
-pub fn search_dungeon_clear_last(texts: &[String], search_from: usize) -> Option<usize> {
-    let last = texts.len();
-    // in this case
-    if search_from > last {
-        return None;
-    }
-    let mut clear = None;
-    for (offset, text) in texts[search_from..last].iter().enumerate() {
-        if text.contains(r"ダンジョン成功報酬") {
-            clear = Some(search_from + offset);
-        }
-    }
-    clear
-}
-pub fn engine_get_text(text: &str) -> (Vec<NaiveDateTime>, Vec<String>) {
-    let mut texts = vec![];
-    let mut times = vec![];
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"(?P<time>\d{4}-\d{2}-\d{2}	\d{2}:\d{2}:\d{2})\t\[INFO]	(?P<text>.+)")
-                .unwrap();
-    }
-    for caps in RE.captures_iter(text) {
-        let time_text = caps.name("time").unwrap().as_str();
-        texts.push("\t".to_string() + caps.name("text").unwrap().as_str());
-        times.push(NaiveDateTime::parse_from_str(time_text, "%Y-%m-%d	%H:%M:%S").unwrap());
-    }
-    (times, texts)
-}
-
-pub fn engine_get_text3(text: &str) -> Vec<String> {
-    let mut texts = vec![];
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"\d{4}-\d{2}-\d{2}	\d{2}:\d{2}:\d{2}	(?P<text>.+)").unwrap();
-    }
-    for caps in RE.captures_iter(text) {
-        texts.push("\t".to_string() + caps.name("text").unwrap().as_str());
-    }
-    texts
-}
-
-pub fn engine_get_text2(text: &str) -> Vec<String> {
-    let mut texts = Vec::new();
-    let longtext;
-    //\r\nを削除する代わりに<ls>をログの区切りとする.
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"(?P<time>\d{4}-\d{2}-\d{2}	\d{2}:\d{2}:\d{2})").unwrap();
-    }
-    //<ls>\date\time text
-    longtext = RE
-        .replace_all(text, |caps: &Captures| {
-            format!("<ls>{}", caps.name("time").unwrap().as_str())
-        })
-        .replace("\n", "")
-        .replace("\r", "");
-    for text in longtext.split("<ls>") {
-        texts.push(text.to_string());
-    }
-
-    texts
-}
-
-pub fn engine_get_info(texts: Vec<String>) -> Vec<String> {
-    let mut vec = Vec::new();
-    lazy_static! {
-        static ref RE: Regex =
-            Regex::new(r"(?P<time>\d{4}-\d{2}-\d{2}	\d{2}:\d{2}:\d{2})\t\[INFO]\t(?P<text>.+)")
-                .unwrap();
-    }
-    for text in texts {
-        match RE.captures(text.as_str()) {
-            None => {}
-            Some(caps) => vec.push(caps.name("text").unwrap().as_str().to_string()),
-        }
-    }
-    vec
+pub fn search_reward_sell_first(events: &[TimedEvent], search_from: usize) -> Option<usize> {
+    search_first(events, search_from, LogEvent::is_reward_sell)
 }
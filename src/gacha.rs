@@ -0,0 +1,305 @@
+use chrono::{Duration, NaiveDateTime};
+use serde::Serialize;
+
+use crate::engines::{add_to_table, InnerStatics};
+use crate::log_event::{LogEvent, TimedEvent};
+use crate::utils::format_time;
+
+/// これより間が空いたら別のセッションとする(秒)
+pub const SESSION_GAP: i64 = 60;
+/// 使用したアイテムのうちガチャを回すためのもの
+const MACHINE_WORDS: [&str; 3] = ["ガチャ", "カプセル", "チケット"];
+/// 機械がわからない
+pub const UNKNOWN_MACHINE: &str = "不明";
+
+/// ガチャを1回まわした結果
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Pull {
+    pub time: NaiveDateTime,
+    /// 直前に使用したチケットやカプセル
+    pub machine: String,
+    /// 当たりのメッセージがなければNone
+    pub name: Option<String>,
+    /// このまわしのためにチケットを使ったか
+    pub spent: bool,
+}
+
+fn is_machine(name: &str) -> bool {
+    MACHINE_WORDS.iter().any(|word| name.contains(word))
+}
+
+/// チケットやカプセルの使用を1回のまわしとして,
+/// SESSION_GAP秒以内の [X] が当たりました！ をその結果とする
+/// チケットなしの当たりや,1回のチケットで続けて出た当たりは別のまわしとする
+pub fn engine_gacha_pulls(events: &[TimedEvent], from: usize) -> Vec<Pull> {
+    let mut pulls: Vec<Pull> = Vec::new();
+    if from > events.len() {
+        return pulls;
+    }
+    //(使用した時刻,名前)
+    let mut machine: Option<(NaiveDateTime, String)> = None;
+    for event in &events[from..] {
+        match event.event {
+            LogEvent::ItemUse { ref name } if is_machine(name) => {
+                machine = Some((event.time, name.clone()));
+                pulls.push(Pull {
+                    time: event.time,
+                    machine: name.clone(),
+                    name: None,
+                    spent: true,
+                });
+            }
+            LogEvent::GachaWin { ref name } => {
+                let used = match machine {
+                    Some((time, ref used))
+                        if event.time - time <= Duration::seconds(SESSION_GAP) =>
+                    {
+                        Some(used.clone())
+                    }
+                    _ => None,
+                };
+                //最後のまわしがこの機械でまだ結果がなければそれに付ける
+                match pulls.last_mut() {
+                    Some(pull) if used.is_some() && pull.spent && pull.name.is_none() => {
+                        pull.name = Some(name.clone());
+                    }
+                    _ => pulls.push(Pull {
+                        time: event.time,
+                        machine: used.unwrap_or_else(|| UNKNOWN_MACHINE.to_string()),
+                        name: Some(name.clone()),
+                        spent: false,
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+    pulls
+}
+
+/// 続けてまわしたひとまとまり
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct GachaSession {
+    pub start: String,
+    pub end: String,
+    pub machine: String,
+    pub pulls: usize,
+    /// 使用したチケットやカプセルの数
+    pub spent: usize,
+    pub results: InnerStatics,
+}
+
+/// 機械ごとのアイテムの出現率
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ItemRate {
+    pub machine: String,
+    pub name: String,
+    pub count: usize,
+    /// その機械をまわした回数
+    pub pulls: usize,
+    pub rate: f64,
+    /// 95%信頼区間(Wilson)
+    pub low: f64,
+    pub high: f64,
+    /// 当たらなかった回数の最長
+    pub longest_drought: usize,
+    /// 最後に当たってからの回数
+    pub since_last: usize,
+    /// サーバー全体のレアガチャで観測された回数
+    pub server: Option<isize>,
+}
+
+/// ガチャの分析
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GachaAnalytics {
+    pub pulls: usize,
+    pub spent: usize,
+    pub sessions: Vec<GachaSession>,
+    /// 機械,回数の多い順
+    pub items: Vec<ItemRate>,
+}
+
+impl GachaAnalytics {
+    /// pullsは時刻順
+    /// rareを渡すとサーバー全体の回数を並べる
+    pub fn new(pulls: &[Pull], rare: Option<&InnerStatics>) -> Self {
+        let mut analytics = Self {
+            pulls: pulls.len(),
+            spent: pulls.iter().filter(|pull| pull.spent).count(),
+            ..Self::default()
+        };
+        let mut last: Option<NaiveDateTime> = None;
+        for pull in pulls {
+            let continued = match (analytics.sessions.last(), last) {
+                (Some(session), Some(last)) => {
+                    session.machine == pull.machine
+                        && pull.time - last <= Duration::seconds(SESSION_GAP)
+                }
+                _ => false,
+            };
+            if !continued {
+                analytics.sessions.push(GachaSession {
+                    start: format_time(Some(pull.time)),
+                    end: String::new(),
+                    machine: pull.machine.clone(),
+                    pulls: 0,
+                    spent: 0,
+                    results: InnerStatics::new(),
+                });
+            }
+            last = Some(pull.time);
+            let session = analytics.sessions.last_mut().unwrap();
+            session.end = format_time(Some(pull.time));
+            session.pulls += 1;
+            session.spent += pull.spent as usize;
+            if let Some(ref name) = pull.name {
+                add_to_table(&mut session.results, name, 1);
+            }
+        }
+
+        let mut machines: Vec<&str> = pulls.iter().map(|pull| pull.machine.as_str()).collect();
+        machines.sort_unstable();
+        machines.dedup();
+        for machine in machines {
+            let results: Vec<Option<&str>> = pulls
+                .iter()
+                .filter(|pull| pull.machine == machine)
+                .map(|pull| pull.name.as_deref())
+                .collect();
+            let mut names: Vec<&str> = results.iter().flatten().copied().collect();
+            names.sort_unstable();
+            names.dedup();
+            let mut items: Vec<ItemRate> = names
+                .into_iter()
+                .map(|name| {
+                    let (count, longest_drought, since_last) = droughts(&results, name);
+                    let (low, high) = wilson(count, results.len());
+                    ItemRate {
+                        machine: machine.to_string(),
+                        name: name.to_string(),
+                        count,
+                        pulls: results.len(),
+                        rate: count as f64 / results.len() as f64,
+                        low,
+                        high,
+                        longest_drought,
+                        since_last,
+                        server: rare.map(|rare| rare.get(name).copied().unwrap_or(0)),
+                    }
+                })
+                .collect();
+            items.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+            analytics.items.extend(items);
+        }
+        analytics
+    }
+}
+
+/// (当たった回数,当たらなかった回数の最長,最後に当たってからの回数)
+fn droughts(results: &[Option<&str>], name: &str) -> (usize, usize, usize) {
+    let mut count = 0;
+    let mut longest = 0;
+    let mut current = 0;
+    for result in results {
+        if *result == Some(name) {
+            count += 1;
+            current = 0;
+        } else {
+            current += 1;
+            longest = longest.max(current);
+        }
+    }
+    (count, longest, current)
+}
+
+/// 出現率の95%信頼区間
+pub fn wilson(count: usize, pulls: usize) -> (f64, f64) {
+    if pulls == 0 {
+        return (0.0, 1.0);
+    }
+    const Z: f64 = 1.96;
+    let n = pulls as f64;
+    let p = count as f64 / n;
+    let denominator = 1.0 + Z * Z / n;
+    let center = p + Z * Z / (2.0 * n);
+    let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt();
+    (
+        ((center - margin) / denominator).max(0.0),
+        ((center + margin) / denominator).min(1.0),
+    )
+}
+
+#[cfg(test)]
+mod gacha_test {
+    use chrono::NaiveDate;
+
+    use crate::engines::InnerStatics;
+    use crate::gacha::{engine_gacha_pulls, wilson, GachaAnalytics, UNKNOWN_MACHINE};
+    use crate::log_event::{LogEvent, TimedEvent};
+
+    #[test]
+    fn test_gacha_analytics() {
+        let event = |second, event| TimedEvent {
+            line: second as usize,
+            time: NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                + chrono::Duration::seconds(second),
+            event,
+        };
+        let use_item = |name: &str| LogEvent::ItemUse {
+            name: name.to_string(),
+        };
+        let win = |name: &str| LogEvent::GachaWin {
+            name: name.to_string(),
+        };
+        let events = vec![
+            event(0, use_item("[ガチャチケット]")),
+            event(1, win("ENパック2000")),
+            event(2, use_item("[リペアパック]")),
+            event(3, use_item("[ガチャチケット]")),
+            event(4, win("ENパック2000")),
+            event(5, use_item("[ガチャチケット]")),
+            event(6, win("レアパーツ")),
+            event(7, use_item("[ガチャチケット]")),
+            event(8, win("ENパック2000")),
+            //当たりのメッセージがなくてもまわした回数に数える
+            event(9, use_item("[ガチャチケット]")),
+            //チケットなしで時間が空いた
+            event(200, win("ENパック2000")),
+        ];
+        let pulls = engine_gacha_pulls(&events, 0);
+        assert_eq!(pulls.len(), 6);
+        assert_eq!(pulls[1].machine, "[ガチャチケット]");
+        assert_eq!(pulls[1].name.as_deref(), Some("ENパック2000"));
+        assert_eq!(pulls[4].name, None);
+        assert_eq!(pulls[5].machine, UNKNOWN_MACHINE);
+        assert!(!pulls[5].spent);
+
+        let mut rare = InnerStatics::new();
+        rare.insert("レアパーツ".to_string(), 7);
+        let analytics = GachaAnalytics::new(&pulls, Some(&rare));
+        assert_eq!(analytics.pulls, 6);
+        assert_eq!(analytics.spent, 5);
+        assert_eq!(analytics.sessions.len(), 2);
+        assert_eq!(analytics.sessions[0].pulls, 5);
+        assert_eq!(analytics.sessions[0].spent, 5);
+        let rare_parts = analytics
+            .items
+            .iter()
+            .find(|item| item.name == "レアパーツ")
+            .unwrap();
+        assert_eq!(rare_parts.count, 1);
+        assert_eq!(rare_parts.pulls, 5);
+        assert_eq!(rare_parts.longest_drought, 2);
+        assert_eq!(rare_parts.since_last, 2);
+        assert_eq!(rare_parts.rate, 0.2);
+        assert_eq!(rare_parts.server, Some(7));
+        assert!(rare_parts.low < 0.2 && 0.2 < rare_parts.high);
+
+        assert_eq!(wilson(0, 0), (0.0, 1.0));
+        let (low, high) = wilson(50, 100);
+        assert!((low - 0.404).abs() < 0.001 && (high - 0.596).abs() < 0.001);
+    }
+}
@@ -0,0 +1,133 @@
+//! fixturesのShift-JISのログを読んで,結果をgoldenファイルと比べる
+//! UPDATE_GOLDEN=1 cargo test でgoldenファイルを書き直す
+
+use std::fmt::Write;
+
+use crate::engines::{
+    engine_count, engine_get_part, engine_item_get, engine_item_use, engine_kill_self,
+    engine_reward_dungeon, engine_reward_income, InnerStatics,
+};
+use crate::lab::{engine_synthesis, LabLedger};
+use crate::replay::replay;
+use crate::statics_address::StaticsAddress;
+use crate::travel::engine_travel;
+use crate::utils::{format_time, read_events};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/");
+
+fn assert_golden(name: &str, actual: &str) {
+    let path = format!("{}{}.golden", FIXTURES, name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, actual).unwrap();
+    }
+    let expected = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "{} differs from {}", name, path);
+}
+
+/// 名前の順に1行ずつ
+fn write_statics(out: &mut String, label: &str, statics: &InnerStatics) {
+    let mut statics: Vec<_> = statics.iter().collect();
+    statics.sort();
+    for (name, qty) in statics {
+        writeln!(out, "{}\t{}\t{}", label, name, qty).unwrap();
+    }
+}
+
+#[test]
+fn test_engines_golden() {
+    let events = read_events(format!("{}engines.txt", FIXTURES)).unwrap();
+    let mut out = String::new();
+    write_statics(&mut out, "item", &engine_item_get(&events, 0));
+    write_statics(&mut out, "part", &engine_get_part(&events, 0));
+    write_statics(&mut out, "use", &engine_item_use(&events, 0));
+    write_statics(&mut out, "kill", &engine_kill_self(&events, 0));
+    write_statics(
+        &mut out,
+        "gacha",
+        &engine_count(&events, 0, StaticsAddress::Gacha),
+    );
+    write_statics(
+        &mut out,
+        "rare",
+        &engine_count(&events, 0, StaticsAddress::RareGacha),
+    );
+    write_statics(
+        &mut out,
+        "lab",
+        &engine_count(&events, 0, StaticsAddress::Lab),
+    );
+    for recipe in LabLedger::new(&[engine_synthesis(&events, 0)]).recipes {
+        writeln!(
+            out,
+            "recipe\t{}\tattempts {}\tsuccesses {}\tfailures {}\tproduced {}",
+            recipe.recipe, recipe.attempts, recipe.successes, recipe.failures, recipe.produced
+        )
+        .unwrap();
+        for cost in recipe.materials {
+            writeln!(
+                out,
+                "recipe\t{}\t{}\t{}\t{:?}",
+                recipe.recipe, cost.name, cost.consumed, cost.per_success
+            )
+            .unwrap();
+        }
+    }
+    let (rewards, sells) = engine_reward_dungeon(&events, 0);
+    write_statics(&mut out, "reward", &rewards);
+    write_statics(&mut out, "sell", &sells);
+    for statics_address in &[
+        StaticsAddress::Burst,
+        StaticsAddress::Mission,
+        StaticsAddress::Shuttle,
+        StaticsAddress::DungeonClear,
+    ] {
+        write_statics(
+            &mut out,
+            &statics_address.to_string(),
+            &engine_count(&events, 0, *statics_address),
+        );
+    }
+    writeln!(out, "income\t{:?}", engine_reward_income(&events, 0)).unwrap();
+    assert_golden("engines", &out);
+}
+
+#[test]
+fn test_dungeon_runs_golden() {
+    let events = read_events(format!("{}dungeon_runs.txt", FIXTURES)).unwrap();
+    let result = replay(&events);
+    let mut out = result.to_string();
+    for travel in engine_travel(&events, 0) {
+        writeln!(
+            out,
+            "travel\t{}\t{}\t{}",
+            format_time(Some(travel.time)),
+            travel.kind.label(),
+            travel.place.as_deref().unwrap_or("")
+        )
+        .unwrap();
+    }
+    for (number, run) in result.runs.iter().enumerate() {
+        let label = |name: &str| format!("run{}\t{}", number + 1, name);
+        write_statics(&mut out, &label("item"), &run.statics.items);
+        write_statics(&mut out, &label("part"), &run.statics.parts);
+        write_statics(&mut out, &label("use"), &run.statics.item_use);
+        write_statics(&mut out, &label("kill"), &run.statics.kills);
+        write_statics(&mut out, &label("reward"), &run.statics.rewards);
+        write_statics(&mut out, &label("sell"), &run.statics.sells);
+        for (floor, split) in run.statics.splits.iter().enumerate() {
+            writeln!(
+                out,
+                "{}\t{}\t{}s\titems {}\tparts {}\tkills {}",
+                label("floor"),
+                floor + 1,
+                split.duration,
+                split.items,
+                split.parts,
+                split.kills
+            )
+            .unwrap();
+        }
+        writeln!(out, "{}\t{:?}", label("income"), run.statics.income).unwrap();
+    }
+    assert_golden("dungeon_runs", &out);
+}
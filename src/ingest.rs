@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use actix_web::web;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::Context;
+
+/// 過去のログの取り込みの進み具合
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Progress {
+    pub done: usize,
+    pub total: usize,
+}
+
+/// 全ての呼び出しで共有するスレッドプールでファイルを1つずつ処理する
+/// 結果はpathsと同じ順に並ぶ
+pub fn pool<T, F>(paths: &[String], work: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(&str) -> T + Sync,
+{
+    paths.par_iter().map(|path| work(path)).collect()
+}
+
+/// 進み具合をSSEで通知する
+/// ファイルが多いときは1%ごとにまとめる
+pub fn report(context: &Context, progress: Progress) {
+    let step = (progress.total / 100).max(1);
+    if progress.done % step == 0 || progress.done == progress.total {
+        context.broadcaster.send("progress", &progress);
+    }
+}
+
+/// 起動時に過去のログをデータベースに取り込んでおく
+pub fn ingest_history(context: web::Data<Context>) {
+    std::thread::spawn(move || {
+        let config = match context.config() {
+            Some(config) => config,
+            None => return,
+        };
+        let files = context.chat_files(&config);
+        ingest_files(&context, &files.finished, |progress| {
+            report(&context, progress)
+        });
+    });
+}
+
+/// ログファイルをデータベースに取り込む
+/// 1つ終わるたびにon_progressを呼ぶ
+pub fn ingest_files<P>(context: &Context, paths: &[String], on_progress: P)
+where
+    P: Fn(Progress) + Sync,
+{
+    let total = paths.len();
+    let done = AtomicUsize::new(0);
+    pool(paths, |path| {
+        if let Err(error) = context.store.ingest(path) {
+            eprintln!("{}", error);
+        }
+        let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+        on_progress(Progress { done, total });
+    });
+}
+
+#[cfg(test)]
+mod ingest_test {
+    use crate::ingest::pool;
+
+    #[test]
+    fn test_pool_keeps_every_result() {
+        let paths: Vec<String> = (0..37).map(|index| index.to_string()).collect();
+        let results = pool(&paths, |path| path.parse::<usize>().unwrap());
+        assert_eq!(results, (0..37).collect::<Vec<_>>());
+    }
+}
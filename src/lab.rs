@@ -0,0 +1,280 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::engines::{add_to_table, InnerStatics};
+use crate::log_event::{LogEvent, TimedEvent};
+
+/// 作成品がわからない失敗
+pub const UNKNOWN_RECIPE: &str = "不明";
+
+/// 材料の載っていない失敗を直前の合成と同じレシピとみなす間隔(秒)
+const REPEAT_WINDOW: i64 = 60;
+
+/// 1回の合成
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Synthesis {
+    pub time: NaiveDateTime,
+    /// 成功したときの作成品と個数
+    pub product: Option<(String, isize)>,
+    /// 消費した材料と個数
+    pub materials: Vec<(String, isize)>,
+}
+
+/// 合成の成功と失敗を順に並べる
+pub fn engine_synthesis(events: &[TimedEvent], from: usize) -> Vec<Synthesis> {
+    if from > events.len() {
+        return Vec::new();
+    }
+    events[from..]
+        .iter()
+        .filter_map(|event| {
+            let (product, materials) = match event.event {
+                LogEvent::LabSuccess {
+                    ref name,
+                    qty,
+                    ref materials,
+                } => (Some((name.clone(), qty)), materials),
+                LogEvent::LabFailure { ref materials } => (None, materials),
+                _ => return None,
+            };
+            Some(Synthesis {
+                time: event.time,
+                product,
+                materials: materials.clone(),
+            })
+        })
+        .collect()
+}
+
+/// 材料ごとの消費
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MaterialCost {
+    pub name: String,
+    pub consumed: isize,
+    /// 1回成功するのに必要な個数の期待値
+    pub per_success: Option<f64>,
+}
+
+/// レシピ(作成品)ごとの合成の記録
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecipeLedger {
+    pub recipe: String,
+    pub attempts: usize,
+    pub successes: usize,
+    pub failures: usize,
+    /// 作成した個数
+    pub produced: isize,
+    pub rate: f64,
+    /// 消費の多い順
+    pub materials: Vec<MaterialCost>,
+}
+
+/// 合成の記録
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LabLedger {
+    pub attempts: usize,
+    pub successes: usize,
+    pub failures: usize,
+    pub rate: Option<f64>,
+    /// 試した回数の多い順
+    pub recipes: Vec<RecipeLedger>,
+    /// 全てのレシピで消費した材料
+    pub materials: InnerStatics,
+}
+
+impl LabLedger {
+    /// sessionsはログファイルごとの時刻順の合成
+    /// 失敗のログには作成品がないので,同じ材料で成功したレシピに数える
+    /// 材料も載っていなければ,同じセッションで直前に合成したレシピとする
+    pub fn new(sessions: &[Vec<Synthesis>]) -> Self {
+        let mut known: Vec<(Vec<(String, isize)>, &str)> = Vec::new();
+        for synthesis in sessions.iter().flatten() {
+            if let Some((ref name, _)) = synthesis.product {
+                let materials = sorted(&synthesis.materials);
+                if !materials.is_empty() && !known.iter().any(|(known, _)| *known == materials) {
+                    known.push((materials, name));
+                }
+            }
+        }
+
+        let mut ledger = Self::default();
+        for syntheses in sessions {
+            let mut previous: Option<(NaiveDateTime, &str)> = None;
+            for synthesis in syntheses {
+                let recipe = ledger.record(synthesis, &known, previous);
+                previous = Some((synthesis.time, recipe));
+            }
+        }
+
+        for entry in &mut ledger.recipes {
+            entry.rate = entry.successes as f64 / entry.attempts as f64;
+            for cost in &mut entry.materials {
+                if entry.successes > 0 {
+                    cost.per_success = Some(cost.consumed as f64 / entry.successes as f64);
+                }
+            }
+            entry.materials.sort_by(|a, b| {
+                b.consumed
+                    .cmp(&a.consumed)
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            ledger.attempts += entry.attempts;
+            ledger.successes += entry.successes;
+            ledger.failures += entry.failures;
+        }
+        if ledger.attempts > 0 {
+            ledger.rate = Some(ledger.successes as f64 / ledger.attempts as f64);
+        }
+        ledger.recipes.sort_by(|a, b| {
+            b.attempts
+                .cmp(&a.attempts)
+                .then_with(|| a.recipe.cmp(&b.recipe))
+        });
+        ledger
+    }
+    /// 1回の合成をレシピに数えて,数えたレシピを返す
+    fn record<'a>(
+        &mut self,
+        synthesis: &'a Synthesis,
+        known: &[(Vec<(String, isize)>, &'a str)],
+        previous: Option<(NaiveDateTime, &'a str)>,
+    ) -> &'a str {
+        let recipe = match synthesis.product {
+            Some((ref name, _)) => name.as_str(),
+            //時間が空いていれば別のものを作り始めたかもしれない
+            None if synthesis.materials.is_empty() => match previous {
+                Some((time, recipe))
+                    if synthesis.time - time <= chrono::Duration::seconds(REPEAT_WINDOW) =>
+                {
+                    recipe
+                }
+                _ => UNKNOWN_RECIPE,
+            },
+            None => {
+                let materials = sorted(&synthesis.materials);
+                known
+                    .iter()
+                    .find(|(known, _)| *known == materials)
+                    .map_or(UNKNOWN_RECIPE, |(_, name)| name)
+            }
+        };
+        let entry = match self
+            .recipes
+            .iter_mut()
+            .position(|entry| entry.recipe == recipe)
+        {
+            Some(position) => &mut self.recipes[position],
+            None => {
+                self.recipes.push(RecipeLedger {
+                    recipe: recipe.to_string(),
+                    attempts: 0,
+                    successes: 0,
+                    failures: 0,
+                    produced: 0,
+                    rate: 0.0,
+                    materials: Vec::new(),
+                });
+                self.recipes.last_mut().unwrap()
+            }
+        };
+        entry.attempts += 1;
+        match synthesis.product {
+            Some((_, qty)) => {
+                entry.successes += 1;
+                entry.produced += qty;
+            }
+            None => entry.failures += 1,
+        }
+        for (name, qty) in &synthesis.materials {
+            match entry.materials.iter_mut().find(|cost| cost.name == *name) {
+                Some(cost) => cost.consumed += qty,
+                None => entry.materials.push(MaterialCost {
+                    name: name.clone(),
+                    consumed: *qty,
+                    per_success: None,
+                }),
+            }
+            add_to_table(&mut self.materials, name, *qty);
+        }
+        recipe
+    }
+}
+
+/// 材料の並びによらず比べる
+fn sorted(materials: &[(String, isize)]) -> Vec<(String, isize)> {
+    let mut materials = materials.to_vec();
+    materials.sort();
+    materials
+}
+
+#[cfg(test)]
+mod lab_test {
+    use crate::lab::{engine_synthesis, LabLedger, UNKNOWN_RECIPE};
+    use crate::log_event::EventParser;
+
+    #[test]
+    fn test_lab_ledger() {
+        let text = "2021-01-01\t00:00:00\t[INFO]\t[合成品] × 2 の作成に成功しました。\r\n\
+                    有機的な破片 × 4\r\n\
+                    金属片 × 1\r\n\
+                    2021-01-01\t00:00:10\t[INFO]\t合成に失敗しました。\r\n\
+                    金属片 × 1\r\n\
+                    有機的な破片 × 4\r\n\
+                    2021-01-01\t00:00:20\t[INFO]\t合成に失敗しました\r\n\
+                    2021-01-01\t00:00:30\t[INFO]\t合成に失敗しました。\r\n\
+                    謎の結晶 × 1\r\n";
+        let syntheses = engine_synthesis(&EventParser::new().parse(text), 0);
+        assert_eq!(syntheses.len(), 4);
+        assert_eq!(
+            syntheses[0].materials,
+            vec![("有機的な破片".to_string(), 4), ("金属片".to_string(), 1)]
+        );
+
+        let ledger = LabLedger::new(std::slice::from_ref(&syntheses));
+        assert_eq!(
+            (ledger.attempts, ledger.successes, ledger.failures),
+            (4, 1, 3)
+        );
+        assert_eq!(ledger.rate, Some(0.25));
+        assert_eq!(ledger.materials["有機的な破片"], 8);
+        //同じ材料の失敗と,材料のない失敗は[合成品]に数える
+        let recipe = &ledger.recipes[0];
+        assert_eq!(recipe.recipe, "[合成品]");
+        assert_eq!((recipe.attempts, recipe.failures), (3, 2));
+        assert_eq!(recipe.produced, 2);
+        assert!((recipe.rate - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(recipe.materials[0].name, "有機的な破片");
+        assert_eq!(recipe.materials[0].consumed, 8);
+        assert_eq!(recipe.materials[0].per_success, Some(8.0));
+        //知らない材料の失敗
+        assert_eq!(ledger.recipes[1].recipe, UNKNOWN_RECIPE);
+        assert_eq!(ledger.recipes[1].materials[0].per_success, None);
+
+        //間が空いた失敗と,別のセッションの最初の失敗は前のレシピに数えない
+        let text = "2021-01-01\t00:02:00\t[INFO]\t合成に失敗しました\r\n";
+        let late = engine_synthesis(&EventParser::new().parse(text), 0);
+        let text = "2021-01-01\t00:00:40\t[INFO]\t合成に失敗しました\r\n";
+        let next_session = engine_synthesis(&EventParser::new().parse(text), 0);
+        //00:00:20の[合成品]の失敗から100秒後
+        let mut same_session = syntheses[..3].to_vec();
+        same_session.extend(late);
+        let ledger = LabLedger::new(&[same_session, next_session]);
+        assert_eq!(ledger.recipes[0].recipe, "[合成品]");
+        assert_eq!(ledger.recipes[0].attempts, 3);
+        assert_eq!(ledger.recipes[1].recipe, UNKNOWN_RECIPE);
+        assert_eq!(ledger.recipes[1].attempts, 2);
+
+        //数字で始まる材料が前の行の個数につながらない
+        let text = "2021-01-01\t00:00:40\t[INFO]\t合成に失敗しました。\r\n\
+                    有機的な破片 × 4\r\n\
+                    2ndパーツ × 1\r\n";
+        let syntheses = engine_synthesis(&EventParser::new().parse(text), 0);
+        assert_eq!(
+            syntheses[0].materials,
+            vec![
+                ("有機的な破片".to_string(), 4),
+                ("2ndパーツ".to_string(), 1)
+            ]
+        );
+    }
+}
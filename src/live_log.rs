@@ -0,0 +1,194 @@
+use std::path::Path;
+
+use crate::dungeon_state_machine::{AbortReason, DungeonRun, DungeonStateMachine};
+use crate::engines::{add_to_table, InnerStatics};
+use crate::log_event::{EventParser, TimedEvent};
+use crate::log_record::last_record_start;
+use crate::statics_address::StaticsAddress;
+use crate::tailer::LogTailer;
+use crate::time_range::TimeRange;
+
+/// Context.general_statics と同じ添字で持つ
+const STATICS_LEN: usize = 16;
+
+/// 最新のログファイルを追いかけて,追記された行を一度だけイベントに分類して
+/// 全てのカウンタと状態機械に渡す
+pub struct LiveLog {
+    tailer: Option<LogTailer>,
+    /// ファイルの切り替えか読み直しのたびに増える
+    generation: usize,
+    initialized: bool,
+    parser: EventParser,
+    events: Vec<TimedEvent>,
+    //複数行にまたがる可能性のある最後のレコード
+    pending_record: String,
+    statics: Vec<InnerStatics>,
+    //まだ通知していない増分
+    deltas: Vec<InnerStatics>,
+    grown: bool,
+    state_machine: DungeonStateMachine,
+    //切り替え前のファイルで終わった周回
+    finished_runs: Vec<DungeonRun>,
+}
+
+impl LiveLog {
+    pub fn new() -> Self {
+        Self {
+            tailer: None,
+            generation: 0,
+            initialized: false,
+            parser: EventParser::new(),
+            events: Vec::new(),
+            pending_record: String::new(),
+            statics: vec![InnerStatics::new(); STATICS_LEN],
+            deltas: vec![InnerStatics::new(); STATICS_LEN],
+            grown: false,
+            state_machine: DungeonStateMachine::init(vec![], 0),
+            finished_runs: Vec::new(),
+        }
+    }
+    fn clear(&mut self) {
+        //ダンジョンの途中でファイルが変わったら切断とする
+        self.state_machine.abort(AbortReason::Disconnect);
+        self.finished_runs.extend(self.state_machine.take_runs());
+        self.generation += 1;
+        self.initialized = false;
+        self.parser = EventParser::new();
+        self.events.clear();
+        self.pending_record.clear();
+        self.statics = vec![InnerStatics::new(); STATICS_LEN];
+        self.deltas = vec![InnerStatics::new(); STATICS_LEN];
+        self.grown = false;
+    }
+    /// 追いかけるファイルを切り替える
+    pub fn follow<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if let Some(ref tailer) = self.tailer {
+            if tailer.path() == path {
+                return;
+            }
+        }
+        self.tailer.replace(LogTailer::new(path));
+        self.clear();
+    }
+    /// ログファイルがなくなった
+    pub fn unfollow(&mut self) {
+        if self.tailer.take().is_some() {
+            self.clear();
+        }
+    }
+    /// 追記分を読み込んで各カウンタと状態機械に渡す
+    /// 追記があればtrue
+    pub fn update(&mut self) -> bool {
+        let tail = match self.tailer.as_mut().map(|tailer| tailer.poll()) {
+            Some(Ok(tail)) => tail,
+            Some(Err(error)) => {
+                eprintln!("{}", error);
+                return false;
+            }
+            None => return false,
+        };
+        if tail.reset {
+            self.clear();
+        }
+        let records = self.complete_records(&tail.lines);
+        let events = self.parser.parse(&records);
+        let grown = !events.is_empty();
+        if grown {
+            let mut deltas = vec![InnerStatics::new(); STATICS_LEN];
+            for event in &events {
+                for statics_address in StaticsAddress::COUNTERS
+                    .iter()
+                    .chain(&[StaticsAddress::DungeonReward, StaticsAddress::DungeonSell])
+                {
+                    if let Some((name, qty)) = event.event.count(*statics_address) {
+                        add_to_table(&mut deltas[statics_address.as_uint()], name, qty);
+                    }
+                }
+            }
+            for (delta, (statics, pending)) in deltas
+                .iter()
+                .zip(self.statics.iter_mut().zip(self.deltas.iter_mut()))
+            {
+                for (name, qty) in delta {
+                    add_to_table(statics, name, *qty);
+                    add_to_table(pending, name, *qty);
+                }
+            }
+            self.grown = true;
+            if self.initialized {
+                self.state_machine.feed(&events, |_, _, _| {});
+            }
+            self.events.extend(events);
+        }
+        //ファイルを開いた時点より前のログではダンジョンを検出しない
+        if !self.initialized {
+            self.state_machine = DungeonStateMachine::init(self.events.clone(), self.events.len());
+            self.initialized = true;
+        }
+        self.state_machine.advance(|_, _, _| {});
+        grown
+    }
+    /// 次のタイムスタンプが現れるまでレコードは完結しないので,最後のレコードは次回に回す
+    /// 追記がなければ書き込みは終わっているとみなして最後のレコードも渡す
+    fn complete_records(&mut self, lines: &[String]) -> String {
+        if lines.is_empty() {
+            return std::mem::take(&mut self.pending_record);
+        }
+        for line in lines {
+            self.pending_record.push_str(line);
+            self.pending_record.push('\n');
+        }
+        match last_record_start(&self.pending_record) {
+            Some(last) if last > 0 => {
+                let rest = self.pending_record.split_off(last);
+                std::mem::replace(&mut self.pending_record, rest)
+            }
+            _ => String::new(),
+        }
+    }
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+    /// 最新のログだけの統計
+    pub fn statics(&self, statics_address: StaticsAddress) -> &InnerStatics {
+        &self.statics[statics_address.as_uint()]
+    }
+    /// 最新のログの期間内の統計
+    pub fn statics_in(&self, statics_address: StaticsAddress, range: &TimeRange) -> InnerStatics {
+        let mut table = InnerStatics::new();
+        for event in &self.events {
+            if !range.contains(event.time) {
+                continue;
+            }
+            if let Some((name, qty)) = event.event.count(statics_address) {
+                add_to_table(&mut table, name, qty);
+            }
+        }
+        table
+    }
+    /// 前回の呼び出しからの増分を取り出す
+    /// 追記がなければNone
+    pub fn take_deltas(&mut self) -> Option<Vec<InnerStatics>> {
+        if !std::mem::replace(&mut self.grown, false) {
+            return None;
+        }
+        Some(std::mem::replace(
+            &mut self.deltas,
+            vec![InnerStatics::new(); STATICS_LEN],
+        ))
+    }
+    /// 最新のログの全てのイベント
+    pub fn events(&self) -> &[TimedEvent] {
+        &self.events
+    }
+    pub fn state_machine(&mut self) -> &mut DungeonStateMachine {
+        &mut self.state_machine
+    }
+    /// まだ保存していない終わった周回
+    pub fn take_runs(&mut self) -> Vec<DungeonRun> {
+        let mut runs = std::mem::take(&mut self.finished_runs);
+        runs.append(&mut self.state_machine.take_runs());
+        runs
+    }
+}
@@ -0,0 +1,314 @@
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+use crate::log_record::{LogRecord, RecordReader};
+use crate::rules::{self, Rules};
+use crate::statics_address::StaticsAddress;
+
+/// ログの1レコードから読み取れる出来事
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LogEvent {
+    ItemGet {
+        name: String,
+        qty: isize,
+    },
+    PartGet {
+        name: String,
+    },
+    ItemUse {
+        name: String,
+    },
+    Kill {
+        name: String,
+    },
+    GachaWin {
+        name: String,
+    },
+    RareGachaWin {
+        name: String,
+    },
+    LabSuccess {
+        name: String,
+        qty: isize,
+        /// 消費した材料と個数
+        materials: Vec<(String, isize)>,
+    },
+    /// 合成に失敗しました
+    LabFailure {
+        materials: Vec<(String, isize)>,
+    },
+    DungeonClear,
+    DungeonReward {
+        name: String,
+        qty: isize,
+    },
+    DungeonSell {
+        name: String,
+        qty: isize,
+    },
+    /// 報酬売却計
+    SellTotal {
+        /// 売却額の合計(ドル)
+        amount: isize,
+    },
+    FloorGate {
+        name: String,
+    },
+    /// それ以外のメッセージ
+    /// keyはタブを\tに置き換えたものでtsvの辞書のキーと同じ形式
+    ScriptedMessage {
+        key: String,
+    },
+}
+
+/// タイムスタンプと行番号付きの出来事
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TimedEvent {
+    /// ファイル内のレコードの番号
+    pub line: usize,
+    pub time: NaiveDateTime,
+    pub event: LogEvent,
+}
+
+impl LogEvent {
+    /// カウンタに加算する名前と個数
+    pub fn count(&self, statics_address: StaticsAddress) -> Option<(&str, isize)> {
+        match (statics_address, self) {
+            (StaticsAddress::Item, LogEvent::ItemGet { name, qty }) => Some((name, *qty)),
+            (StaticsAddress::Parts, LogEvent::PartGet { name }) => Some((name, 1)),
+            (StaticsAddress::ItemUse, LogEvent::ItemUse { name }) => Some((name, 1)),
+            (StaticsAddress::Kill, LogEvent::Kill { name }) => Some((name, 1)),
+            (StaticsAddress::Gacha, LogEvent::GachaWin { name }) => Some((name, 1)),
+            (StaticsAddress::RareGacha, LogEvent::RareGachaWin { name }) => Some((name, 1)),
+            (StaticsAddress::Lab, LogEvent::LabSuccess { name, qty, .. }) => Some((name, *qty)),
+            (StaticsAddress::DungeonReward, LogEvent::DungeonReward { name, qty }) => {
+                Some((name, *qty))
+            }
+            (StaticsAddress::DungeonSell, LogEvent::DungeonSell { name, qty }) => {
+                Some((name, *qty))
+            }
+            (
+                StaticsAddress::Burst
+                | StaticsAddress::Mission
+                | StaticsAddress::DungeonClear
+                | StaticsAddress::Shuttle,
+                LogEvent::ScriptedMessage { key },
+            ) => statics_address
+                .dictionary()?
+                .get(key)
+                .map(|name| (name.as_str(), 1)),
+            _ => None,
+        }
+    }
+    /// "報酬"を含む行
+    pub fn is_reward(&self) -> bool {
+        matches!(
+            self,
+            LogEvent::DungeonClear
+                | LogEvent::DungeonReward { .. }
+                | LogEvent::DungeonSell { .. }
+                | LogEvent::SellTotal { .. }
+        )
+    }
+    /// "報酬売却"を含む行
+    pub fn is_reward_sell(&self) -> bool {
+        matches!(
+            self,
+            LogEvent::DungeonSell { .. } | LogEvent::SellTotal { .. }
+        )
+    }
+}
+
+/// [INFO]の本文を分類する
+/// 合成の結果は複数行にまたがるので,続きの行を改行でつなげたものを渡す
+pub fn classify_info(text: &str) -> Option<LogEvent> {
+    classify_info_with(text, &rules::current())
+}
+
+/// ダンジョンの報酬の文言はrulesに従う
+pub fn classify_info_with(text: &str, rules: &Rules) -> Option<LogEvent> {
+    lazy_static! {
+        static ref RE_FLOOR: Regex = Regex::new(r"(?P<name>.+?)がフロアゲートを起動した！").unwrap();
+        static ref RE_RARE: Regex =
+            Regex::new(r"誰かが \[(?P<name>.+)] をガチャセンターで当てました！").unwrap();
+        static ref RE_GACHA: Regex = Regex::new(r"\[(?P<name>.+)] が当たりました！").unwrap();
+        static ref RE_ITEM_GET: Regex =
+            Regex::new(r"(?P<name>\[.+]) を (?P<N>\d+)個 取得した！").unwrap();
+        static ref RE_PART: Regex = Regex::new(r"(?P<name>\[.+]) を取得した！").unwrap();
+        //[リペアパック2000] を使用した！
+        static ref RE_ITEM_USE: Regex = Regex::new(r"(?P<name>\[.+]) を使用した！").unwrap();
+        static ref RE_KILL: Regex = Regex::new(r"^(?P<name>[^が]+?)を撃破した").unwrap();
+        //1個も合成に成功しないなら
+        // 合成に失敗しました
+        //1個でも合成に成功したら
+        // (?P<name>) × \d+ の作成に成功しました。有機的な破片 × 4
+        static ref RE_LAB: Regex =
+            Regex::new(r"(?P<name>.+) の作成に成功しました。(?s:(?P<materials>.*))").unwrap();
+        static ref RE_LAB_QTY: Regex = Regex::new(r"(?P<name>.+) × (?P<N>[0-9]+)").unwrap();
+        static ref RE_LAB_FAILURE: Regex = Regex::new(r"合成に失敗しました。?(?s:(?P<materials>.*))").unwrap();
+    }
+    let name_qty = |caps: regex::Captures| {
+        (
+            caps.name("name").unwrap().as_str().to_string(),
+            caps.name("N").unwrap().as_str().parse::<isize>().unwrap(),
+        )
+    };
+    let name = |caps: regex::Captures| caps.name("name").unwrap().as_str().to_string();
+
+    if let Some(caps) = RE_FLOOR.captures(text) {
+        return Some(LogEvent::FloorGate { name: name(caps) });
+    }
+    if let Some(caps) = RE_RARE.captures(text) {
+        return Some(LogEvent::RareGachaWin { name: name(caps) });
+    }
+    if let Some(caps) = RE_GACHA.captures(text) {
+        return Some(LogEvent::GachaWin { name: name(caps) });
+    }
+    if let Some(caps) = RE_ITEM_GET.captures(text) {
+        let (name, qty) = name_qty(caps);
+        return Some(LogEvent::ItemGet { name, qty });
+    }
+    if let Some(caps) = RE_PART.captures(text) {
+        return Some(LogEvent::PartGet { name: name(caps) });
+    }
+    if let Some(caps) = RE_ITEM_USE.captures(text) {
+        return Some(LogEvent::ItemUse { name: name(caps) });
+    }
+    if let Some(caps) = RE_KILL.captures(text) {
+        return Some(LogEvent::Kill { name: name(caps) });
+    }
+    if let Some(caps) = RE_LAB.captures(text) {
+        let name = caps.name("name").unwrap().as_str();
+        let (name, qty) = match RE_LAB_QTY.captures(name) {
+            //新しいバージョンのログ
+            Some(caps) => name_qty(caps),
+            //古いバージョンのログ
+            None => (name.to_string(), 1),
+        };
+        let materials = lab_materials(&caps["materials"]);
+        return Some(LogEvent::LabSuccess {
+            name,
+            qty,
+            materials,
+        });
+    }
+    if let Some(caps) = RE_LAB_FAILURE.captures(text) {
+        return Some(LogEvent::LabFailure {
+            materials: lab_materials(&caps["materials"]),
+        });
+    }
+    //報酬売却計 12,345
+    //金額が読めなければ0ドルとはせずに分類しない
+    if text.contains(&rules.dungeon.sell_total) {
+        let caps = rules.sell_total.captures(text)?;
+        let amount = caps["N"].replace(',', "").parse::<isize>().ok()?;
+        return Some(LogEvent::SellTotal { amount });
+    }
+    if let Some(caps) = rules.sell.captures(text) {
+        let (name, qty) = name_qty(caps);
+        return Some(LogEvent::DungeonSell { name, qty });
+    }
+    //報酬－ ENパック2000 x 1
+    if let Some(caps) = rules.reward.captures(text) {
+        let (name, qty) = name_qty(caps);
+        return Some(LogEvent::DungeonReward { name, qty });
+    }
+    if text.contains(&rules.dungeon.clear) {
+        return Some(LogEvent::DungeonClear);
+    }
+    None
+}
+
+/// 材料は1行に1つ 有機的な破片 × 4
+fn lab_materials(text: &str) -> Vec<(String, isize)> {
+    lazy_static! {
+        static ref RE_MATERIAL: Regex = Regex::new(r"^(?P<name>.+) × (?P<N>[0-9]+)$").unwrap();
+    }
+    text.lines()
+        .filter_map(|line| {
+            let caps = RE_MATERIAL.captures(line.trim())?;
+            Some((caps["name"].to_string(), caps["N"].parse().ok()?))
+        })
+        .collect()
+}
+
+/// レコードを分類する
+pub fn classify(record: &LogRecord) -> LogEvent {
+    if record.channel.as_deref() == Some("INFO") {
+        if let Some(event) = classify_info(&record.body) {
+            return event;
+        }
+    }
+    LogEvent::ScriptedMessage {
+        key: record.message().replace('\t', "\\t"),
+    }
+}
+
+/// ログを1度だけ読んで全てのレコードを分類する
+pub struct EventParser {
+    reader: RecordReader,
+}
+
+impl EventParser {
+    pub fn new() -> Self {
+        Self {
+            reader: RecordReader::new(),
+        }
+    }
+    /// 完結したレコードだけを含むテキストを解析する
+    /// 行番号は前回の続きから振る
+    pub fn parse(&mut self, text: &str) -> Vec<TimedEvent> {
+        self.reader
+            .read(text)
+            .iter()
+            .map(|record| TimedEvent {
+                line: record.line,
+                time: record.timestamp,
+                event: classify(record),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod log_event_test {
+    use crate::log_event::{EventParser, LogEvent};
+
+    #[test]
+    fn test_parse_multiline_record() {
+        let text = "2021-01-01\t00:00:00\t[INFO]\t[リペアパック] を使用した！\r\n\
+                    2021-01-01\t00:00:01\t[INFO]\t[合成品] × 2 の作成に成功しました。\r\n\
+                    有機的な破片 × 4\r\n\
+                    2021-01-01\t00:00:02\t[INFO]\t[ポルドゲート]を使用した！\r\n";
+        let mut parser = EventParser::new();
+        let events = parser.parse(text);
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0].event,
+            LogEvent::ItemUse {
+                name: "[リペアパック]".to_string()
+            }
+        );
+        assert_eq!(
+            events[1].event,
+            LogEvent::LabSuccess {
+                name: "[合成品]".to_string(),
+                qty: 2,
+                materials: vec![("有機的な破片".to_string(), 4)]
+            }
+        );
+        assert_eq!(
+            events[2].event,
+            LogEvent::ScriptedMessage {
+                key: "[INFO]\\t[ポルドゲート]を使用した！".to_string()
+            }
+        );
+        let events = parser.parse("2021-01-01\t00:00:03\t[INFO]\t報酬売却計 12,345ドル\r\n");
+        assert_eq!(events[0].event, LogEvent::SellTotal { amount: 12345 });
+        let events = parser.parse("2021-01-01\t00:00:04\t[INFO]\t報酬売却計 ---\r\n");
+        assert!(matches!(events[0].event, LogEvent::ScriptedMessage { .. }));
+        //続きから番号を振る
+        let events = parser.parse("2021-01-01\t00:00:05\t[INFO]\tマッドを撃破した\r\n");
+        assert_eq!(events[0].line, 5);
+    }
+}
@@ -0,0 +1,156 @@
+use chrono::NaiveDateTime;
+use regex::Regex;
+
+lazy_static! {
+    static ref RE_TIME: Regex =
+        Regex::new(r"(?m)^(?P<time>\d{4}-\d{2}-\d{2}	\d{2}:\d{2}:\d{2})\t").unwrap();
+}
+
+/// ログの1レコード
+/// タイムスタンプで始まる行と,それに続くタイムスタンプのない行からなる
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LogRecord {
+    /// ファイル内のレコードの番号
+    pub line: usize,
+    pub timestamp: NaiveDateTime,
+    /// [INFO]などの[]で囲まれた部分
+    pub channel: Option<String>,
+    /// <シャトル乗務員>などの<>で囲まれた発言者
+    pub speaker: Option<String>,
+    /// 続きの行を改行でつなげた本文
+    /// <>で囲まれた発言者は含まない
+    pub body: String,
+    /// ファイルに書かれていたままの行(\rは除く)
+    pub raw: String,
+}
+
+impl LogRecord {
+    fn new(line: usize, timestamp: NaiveDateTime, raw: &str) -> Self {
+        let mut lines = raw.lines();
+        let message = message(lines.next().unwrap_or_default());
+        let (channel, speaker, first) = match message.split_once('\t') {
+            Some((prefix, rest)) if prefix.starts_with('[') && prefix.ends_with(']') => {
+                (Some(prefix[1..prefix.len() - 1].to_string()), None, rest)
+            }
+            Some((prefix, rest)) if prefix.starts_with('<') && prefix.ends_with('>') => {
+                (None, Some(prefix[1..prefix.len() - 1].to_string()), rest)
+            }
+            _ => (None, None, message),
+        };
+        //行の境目がわからなくならないように改行を残す
+        let mut body = first.to_string();
+        lines.for_each(|line| {
+            body.push('\n');
+            body.push_str(line);
+        });
+        Self {
+            line,
+            timestamp,
+            channel,
+            speaker,
+            body,
+            raw: raw.to_string(),
+        }
+    }
+    /// 1行目のタイムスタンプより後ろ
+    pub fn message(&self) -> &str {
+        message(self.raw.lines().next().unwrap_or_default())
+    }
+}
+
+fn message(first_line: &str) -> &str {
+    match RE_TIME.find(first_line) {
+        Some(found) => &first_line[found.end()..],
+        None => first_line,
+    }
+}
+
+/// 最後のレコードの開始位置
+pub(crate) fn last_record_start(text: &str) -> Option<usize> {
+    RE_TIME.find_iter(text).last().map(|found| found.start())
+}
+
+/// ログをレコードに区切る
+/// 最初のタイムスタンプより前の行は捨てる
+pub struct RecordReader {
+    line: usize,
+}
+
+impl RecordReader {
+    pub fn new() -> Self {
+        Self { line: 0 }
+    }
+    /// 完結したレコードだけを含むテキストを読む
+    /// 番号は前回の続きから振る
+    pub fn read(&mut self, text: &str) -> Vec<LogRecord> {
+        let mut records = Vec::new();
+        //(timestamp,raw)
+        let mut record: Option<(NaiveDateTime, String)> = None;
+        for line in text.lines() {
+            let line = line.trim_end_matches('\r');
+            match RE_TIME.captures(line) {
+                Some(caps) => {
+                    if let Some((timestamp, raw)) = record.take() {
+                        records.push(LogRecord::new(self.line, timestamp, &raw));
+                        self.line += 1;
+                    }
+                    let timestamp = NaiveDateTime::parse_from_str(
+                        caps.name("time").unwrap().as_str(),
+                        "%Y-%m-%d	%H:%M:%S",
+                    );
+                    if let Ok(timestamp) = timestamp {
+                        record.replace((timestamp, line.to_string()));
+                    }
+                }
+                None => {
+                    if let Some((_, ref mut raw)) = record {
+                        raw.push('\n');
+                        raw.push_str(line);
+                    }
+                }
+            }
+        }
+        if let Some((timestamp, raw)) = record.take() {
+            records.push(LogRecord::new(self.line, timestamp, &raw));
+            self.line += 1;
+        }
+        records
+    }
+}
+
+#[cfg(test)]
+mod log_record_test {
+    use crate::log_record::RecordReader;
+
+    #[test]
+    fn test_read_records() {
+        let text = "ゴミ\r\n\
+                    2021-01-01\t00:00:00\t<シャトル乗務員>\t当機はただいま到着いたしました。\r\n\
+                    2021-01-01\t00:00:01\t[INFO]\t[合成品] × 2 の作成に成功しました。\r\n\
+                    有機的な破片 × 4\r\n";
+        let records = RecordReader::new().read(text);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].line, 0);
+        assert_eq!(
+            records[0].message(),
+            "<シャトル乗務員>\t当機はただいま到着いたしました。"
+        );
+        assert_eq!(records[0].channel, None);
+        assert_eq!(records[0].speaker.as_deref(), Some("シャトル乗務員"));
+        assert_eq!(records[0].body, "当機はただいま到着いたしました。");
+        assert_eq!(records[1].channel.as_deref(), Some("INFO"));
+        assert_eq!(records[1].speaker, None);
+        assert_eq!(
+            records[1].body,
+            "[合成品] × 2 の作成に成功しました。\n有機的な破片 × 4"
+        );
+        assert_eq!(
+            records[1].message(),
+            "[INFO]\t[合成品] × 2 の作成に成功しました。"
+        );
+        assert_eq!(
+            records[1].raw,
+            "2021-01-01\t00:00:01\t[INFO]\t[合成品] × 2 の作成に成功しました。\n有機的な破片 × 4"
+        );
+    }
+}
@@ -4,68 +4,54 @@ extern crate lazy_static;
 extern crate regex;
 extern crate toml;
 
-use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::fs;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4, TcpListener};
+use std::net::Ipv4Addr;
 use std::path::Path;
 use std::sync::mpsc::Sender;
-
-use regex::Regex;
-
-use engines::engine_item_use;
-use engines::engine_kill_self;
-use engines::engine_labo;
-use utils::connect_hashmap;
-use utils::hashmap_to_vec;
-use utils::sort;
-
-use crate::engines::{
-    engine_gacha, engine_get_part, engine_item_get, engine_reward_dungeon, engine_tsv_match,
-    search_floor_last, DungeonRewardElement, InnerStatics,
-};
-use crate::process_manager::{construct_launcher, update, ProcessRequest};
-use crate::setting::{get_path_from_launcher, Setting};
-use crate::utils::{load_tsv, read_from_file, read_from_file2, read_from_file3, SortTarget};
-use crate::Method::{CONNECT, DELETE, GET, HEAD, POST, PUT, TRACE};
-
-use sailfish::TemplateOnce;
-
-#[derive(TemplateOnce)]
-#[template(path = "general.stpl")]
-struct GeneralStaticsTemplate {
-    name: String,
-    statics: Vec<(String, isize)>,
-}
-
-#[derive(TemplateOnce)]
-#[template(path = "dungeon_reward.stpl")]
-struct GenerateDungeonRewardStaticsTemplate {
-    name: String,
-    statics: Vec<(String, DungeonRewardElement)>,
-}
-#[derive(TemplateOnce)]
-#[template(path = "inner_floor.stpl")]
-struct InFloorStaticsTemplate {
-    name: String,
-    set_of_statics: Vec<GeneralStaticsTemplate>,
-}
-#[derive(TemplateOnce)]
-#[template(path = "dungeon.stpl")]
-struct DungeonStaticsTemplate {
-    lap_time: Option<chrono::Duration>,
-    dungeon_name: String,
-    set_of_statics: Vec<GeneralStaticsTemplate>,
-}
+use std::sync::{Mutex, RwLock};
+
+use actix_web::{web, App, HttpServer};
+
+use crate::cli::Command;
+use crate::engines::InnerStatics;
+use crate::live_log::LiveLog;
+use crate::process_manager::{construct_launcher, ProcessRequest};
+use crate::push::Broadcaster;
+use crate::setting::Setting;
+use crate::store::{EventStore, STORE_PATH};
+use crate::utils::read_events;
+use crate::watcher::{ChatFiles, ChatWatcher};
+
+mod api;
+mod cli;
 mod dungeon_state_machine;
 mod engines;
+mod gacha;
+#[cfg(test)]
+mod golden_test;
+mod ingest;
+mod lab;
+mod live_log;
+mod log_event;
+mod log_record;
 mod mesa_inject;
 mod process_manager;
+mod push;
+mod rare_feed;
+mod rates;
+mod replay;
+mod rules;
+mod server;
+mod session;
 mod setting;
 mod statics_address;
+mod store;
+mod tailer;
+mod time_range;
+mod travel;
 mod utils;
+mod watcher;
 
 #[derive(Clone)]
 struct Statics {
@@ -74,25 +60,6 @@ struct Statics {
     last: usize,
 }
 
-#[derive(Debug)]
-enum Method {
-    GET,
-    POST,
-    PUT,
-    HEAD,
-    DELETE,
-    OPTIONS,
-    TRACE,
-    CONNECT,
-}
-
-#[derive(Debug)]
-struct HttpRequest<'a> {
-    method: Method,
-    uri: String,
-    queries: Vec<(Cow<'a, str>, Option<Cow<'a, str>>)>,
-    version: f32,
-}
 impl Statics {
     fn new() -> Self {
         Self {
@@ -139,707 +106,179 @@ impl Statics {
 
 /// contain all statics log contents
 /// and configs
-/// logs are splatted to each line
-
+/// each part is locked separately so that requests can be served concurrently
 struct Context {
-    config: Option<Setting>,
-    launcher: Option<Sender<ProcessRequest>>,
-    log_cache: HashMap<String, Vec<String>>,
-    general_statics: Vec<Statics>,
-    dungeon_state_machine: DungeonStateMachine,
-    current_updating_file: String,
-    port: u16,
+    config: RwLock<Option<Setting>>,
+    launcher: Mutex<Option<Sender<ProcessRequest>>>,
+    general_statics: Vec<Mutex<Statics>>,
+    live: Mutex<LiveLog>,
+    chat: Mutex<Option<ChatWatcher>>,
+    broadcaster: Broadcaster,
+    store: EventStore,
 }
 
-use crate::dungeon_state_machine::DungeonStateMachine;
-use crate::statics_address::StaticsAddress;
-use chrono::Duration;
-use std::sync::Mutex;
+impl Context {
+    fn config(&self) -> Option<Setting> {
+        self.config.read().unwrap().clone()
+    }
+    /// chatフォルダのログファイル
+    /// まだ監視していなければ監視を始める
+    fn chat_files(&self, config: &Setting) -> ChatFiles {
+        let chat_dir = config.chat_dir();
+        let mut chat = self.chat.lock().unwrap();
+        let watching = match *chat {
+            Some(ref watcher) => watcher.chat_dir() == Path::new(&chat_dir),
+            None => false,
+        };
+        if !watching {
+            chat.replace(ChatWatcher::new(&chat_dir));
+        }
+        chat.as_ref().unwrap().files()
+    }
+}
+
+/// 統計データベースを開く
+/// 開けなければメモリ上に作る
+fn open_store() -> EventStore {
+    EventStore::open(STORE_PATH).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        EventStore::open_in_memory().expect("can not create statics database")
+    })
+}
+
+/// --replay <ログファイル>
+/// 過去のログを状態機械に流して遷移と周回を表示する
+fn replay_command(path: &str) -> std::io::Result<()> {
+    let config = fs::read_to_string("Settings.toml")
+        .ok()
+        .and_then(|text| toml::from_str::<Setting>(&text).ok());
+    if let Some(config) = config {
+        rules::install(&config.rules);
+    }
+    replay::replay(&read_events(path)?).print();
+    Ok(())
+}
 
-fn main() {
+/// 設定を読み込んで統計の入れ物を作る
+/// 設定が読めたらポート番号も返す
+fn load_context() -> (Context, Option<u16>) {
     let config_text = fs::read_to_string("Settings.toml");
     let mut context = Context {
-        config: None,
-        launcher: None,
-        log_cache: Default::default(),
-        general_statics: vec![Statics::new(); 16],
-        dungeon_state_machine: DungeonStateMachine::init(vec![], vec![], 0),
-        current_updating_file: "".to_string(),
-        port: 7878,
+        config: RwLock::new(None),
+        launcher: Mutex::new(None),
+        general_statics: (0..16).map(|_| Mutex::new(Statics::new())).collect(),
+        live: Mutex::new(LiveLog::new()),
+        chat: Mutex::new(None),
+        broadcaster: Broadcaster::new(),
+        store: open_store(),
     };
+    let mut port = None;
     //設定読み込み
     if let Ok(config_text) = config_text {
-        context.config = toml::from_str(&config_text).ok();
-        context.launcher.replace(construct_launcher(
-            context.config.clone().unwrap().base_path,
-        ));
-        context.port = context.config.as_ref().unwrap().port;
-    }
-    let listener =
-        TcpListener::bind(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), context.port)).unwrap();
-    webbrowser::open(&format!("http://localhost:{}/", context.port))
-        .expect("cant not open browser");
-    for stream in listener.incoming() {
-        let mut stream = stream.unwrap();
-        let mut buffer = [0; 1024];
-        let bytes = stream.read(&mut buffer).unwrap();
-        let text = String::from_utf8_lossy(&buffer[0..bytes]);
-        let request = request_parse(text.as_ref());
-
-        match request {
-            None => {}
-            Some(request) => {
-                let response = make_response(request, &mut context);
-                stream.write_all(&response).unwrap();
-                stream.flush().unwrap();
-            }
+        let config: Option<Setting> = toml::from_str(&config_text).ok();
+        if let Some(ref config) = config {
+            context
+                .launcher
+                .get_mut()
+                .unwrap()
+                .replace(construct_launcher(config.base_path.clone()));
+            port = Some(config.port);
+            rules::install(&config.rules);
         }
+        *context.config.get_mut().unwrap() = config;
     }
+    (context, port)
 }
 
-fn request_parse(text: &str) -> Option<HttpRequest> {
-    let re = Regex::new(r"(.+?) (.+?) HTTP/(.+?)\r\n").unwrap();
-    let cap = re.captures(text);
-    match cap {
-        Some(cap) => {
-            let method = &cap[1];
-            let uri = String::from(".");
-            let uri = uri + &cap[2];
-            let uri = uri.as_str();
-            let mut uri_chunks = uri.split('?');
-            let uri = uri_chunks.next().unwrap();
-            let queries: Vec<(Cow<str>, Option<Cow<str>>)> = uri_chunks
-                .map(|s| {
-                    let mut query = s.split('=');
-                    (
-                        Cow::Owned(query.next().unwrap().to_string()),
-                        query.next().map(|v| Cow::Owned(v.to_string())),
-                    )
-                })
-                .collect();
-            let uri = match uri {
-                "./" => "./index.html",
-                _ => &uri,
-            };
-            let method = match method {
-                "GET" => GET,
-                "POST" => POST,
-                "PUT" => PUT,
-                "HEAD" => HEAD,
-                "DELETE" => DELETE,
-                "OPTIONS" => HEAD,
-                "TRACE" => TRACE,
-                "CONNECT" => CONNECT,
-                _ => GET,
-            };
+/// stats,runs
+/// ブラウザを使わずに集計して標準出力に書き出す
+fn print_command<F>(query: F) -> std::io::Result<()>
+where
+    F: FnOnce(&Context) -> Result<String, String>,
+{
+    let (context, _) = load_context();
+    let text = query(&context)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidInput, error))?;
+    println!("{}", text);
+    Ok(())
+}
 
-            Some(HttpRequest {
-                method,
-                uri: uri.to_string(),
-                queries,
-                version: 1.1,
-            })
-        }
-        None => None,
+/// windows_subsystemのままでは標準出力が捨てられる
+/// コマンドとして起動されたときは呼び出し元のコンソールにつなぐ
+#[cfg(windows)]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    //エクスプローラーから起動したときなど,親にコンソールがなければ何もしない
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
     }
 }
 
-fn search_latest_log_file<P: AsRef<Path>>(chat_dir_path: P) -> (String, Vec<String>) {
-    let mut paths = Vec::new(); //パスのリスト
-    let c21_chat_file_list = fs::read_dir(chat_dir_path).unwrap();
-    for dir_entry in c21_chat_file_list {
-        match dir_entry {
-            Ok(dir_entry) => {
-                let path = dir_entry.path();
-                let path = path.to_str().unwrap();
-                let path = path.to_string();
-                let update = std::fs::metadata(&path).unwrap();
-                let update = update.modified().unwrap();
-
-                paths.push((path, update));
-            }
-            Err(error) => {
-                eprintln!("{}", error)
-            }
+#[cfg(not(windows))]
+fn attach_console() {}
+
+async fn serve(no_browser: bool) -> std::io::Result<()> {
+    let (context, port) = load_context();
+    let port = port.unwrap_or(7878);
+    let context = web::Data::new(context);
+    push::follow_log(context.clone());
+    ingest::ingest_history(context.clone());
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(context.clone())
+            .configure(server::routes)
+    })
+    .bind((Ipv4Addr::LOCALHOST, port))?
+    .run();
+    if !no_browser {
+        //ブラウザがない環境ではURLだけ表示する
+        if let Err(error) = webbrowser::open(&format!("http://localhost:{}/", port)) {
+            eprintln!("{}: http://localhost:{}/", error, port);
         }
     }
-    paths.sort_by(|a, b| a.1.cmp(&b.1));
-    let last = paths.pop().unwrap(); //最新
-    let paths: Vec<String> = paths.iter().map(|item| item.0.clone()).collect();
-    (last.0, paths)
+    server.await
 }
 
-//Httpレスポンスを作成
-fn make_response(request: HttpRequest, context: &mut Context) -> Vec<u8> {
-    if let Some(ref mut config) = context.config {
-        let file = File::open(&request.uri);
-        #[cfg(debug_assertions)]
-        println!("request:{:#?}", request);
-        let mut header = Vec::from("HTTP/1.1 200 OK\r\n\r\n");
-        lazy_static! {
-            static ref DICTIONARIES: Vec<HashMap<String, String>> = {
-                let shuttle_tsv = load_tsv("./shuttle.tsv");
-                let dungeon_tsv = load_tsv("./dungeon.tsv");
-                let mission_tsv = load_tsv("./mission.tsv");
-                let burst_tsv = load_tsv("./burst.tsv");
-                vec![burst_tsv, dungeon_tsv, mission_tsv, shuttle_tsv]
-            };
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(error) => {
+            attach_console();
+            eprintln!("{}\n{}", error, cli::USAGE);
+            std::process::exit(2);
         }
-        let mut chat_path = config.base_path.clone();
-        chat_path.push_str("chat/");
-        let chat_dir_path = Path::new(&chat_path);
-        let mut payload = {
-            match file {
-                //ファイルが存在
-                Ok(mut file) => {
-                    let mut buf = Vec::with_capacity(4096);
-                    file.read_to_end(&mut buf).unwrap();
-                    buf
-                }
-                //存在しない
-                Err(err) => {
-                    eprintln!("{}", err);
-                    let uri = request.uri;
-                    match uri.as_str() {
-                        //機能はCGIとして実装
-                        "./dungeon_reward" => {
-                            let (last, paths) = search_latest_log_file(chat_dir_path);
-                            let need_to_load = context.general_statics
-                                [StaticsAddress::DungeonSell.as_uint()]
-                            .query_cache(&paths);
-                            use std::sync::Arc;
-                            let (tx, rx) = std::sync::mpsc::channel();
-                            let tx = Arc::new(Mutex::new(tx));
-                            let ntl = need_to_load.clone();
-                            #[cfg(debug_assertions)]
-                            println!("{:#?}", ntl);
-
-                            for path in ntl {
-                                use std::thread;
-                                let tx = tx.clone();
-                                thread::spawn(move || {
-                                    let texts = read_from_file(&path);
-                                    let data = engine_reward_dungeon(&texts.1, 0);
-                                    tx.lock().unwrap().send(data).unwrap();
-                                });
-                            }
-                            if !need_to_load.is_empty() {
-                                for (id, rcv) in rx.iter().enumerate() {
-                                    #[cfg(debug_assertions)]
-                                    println!("id: {}, len: {}", id + 1, need_to_load.len());
-                                    if id + 1 == need_to_load.len() {
-                                        break;
-                                    }
-                                    context.general_statics
-                                        [StaticsAddress::DungeonReward.as_uint()]
-                                    .update_statics(rcv.0);
-                                    context.general_statics[StaticsAddress::DungeonSell.as_uint()]
-                                        .update_statics(rcv.1);
-                                }
-                            }
-                            let texts = read_from_file(last);
-                            let new_statics = engine_reward_dungeon(&texts.1, 0);
-                            let (reward, sell) = {
-                                (
-                                    context.general_statics
-                                        [StaticsAddress::DungeonReward.as_uint()]
-                                    .get_statics(),
-                                    context.general_statics[StaticsAddress::DungeonSell.as_uint()]
-                                        .get_statics(),
-                                )
-                            };
-
-                            let set_reward = connect_hashmap(new_statics.0, reward);
-                            let set_sell = connect_hashmap(new_statics.1, sell);
-                            let mut vec_reward = hashmap_to_vec(&set_reward);
-                            let mut vec_sell = hashmap_to_vec(&set_sell);
-
-                            sort(&mut vec_reward, SortTarget::NAME, true);
-                            sort(&mut vec_sell, SortTarget::NAME, true);
-                            //ITEMSとLDSを統合して出力
-                            let ctx = InFloorStaticsTemplate {
-                                name: "ダンジョン報酬".to_string(),
-                                set_of_statics: vec![
-                                    GeneralStaticsTemplate {
-                                        name: "報酬".to_string(),
-                                        statics: vec_reward,
-                                    },
-                                    GeneralStaticsTemplate {
-                                        name: "売却".to_string(),
-                                        statics: vec_sell,
-                                    },
-                                ],
-                            };
-                            let text = ctx.render_once().unwrap();
-                            text.into_bytes()
-                        }
-                        "./system" => {
-                            // process query
-                            if let Some(query) = request.queries.get(0) {
-                                match query.0.as_ref() {
-                                    "generate_config" => {
-                                        if let Ok(setting) = get_path_from_launcher() {
-                                            #[cfg(debug_assertions)]
-                                            println!("Setting generated");
-                                            let config_file_content =
-                                                toml::to_string(&setting).unwrap();
-                                            std::fs::write("./Settings.toml", config_file_content)
-                                                .unwrap();
-                                            Vec::from(include_str!("generated_config.html"))
-                                        } else {
-                                            Vec::from(include_str!("blank.html"))
-                                        }
-                                    }
-                                    "inject_mesa" => {
-                                        #[cfg(debug_assertions)]
-                                        println!("Injecting mesa");
-                                        let mut programs_path = config.base_path.clone();
-                                        programs_path.push_str("programs");
-                                        mesa_inject::inject_mesa(programs_path);
-                                        Vec::from(include_str!("injecting_mesa.html"))
-                                    }
-                                    "update_c21" => {
-                                        let _process = update(config);
-                                        Vec::from(include_str!("blank.html"))
-                                    }
-                                    "launch_cosmic" => {
-                                        if let Some(ref mut sender) = context.launcher {
-                                            sender
-                                                .send(ProcessRequest::LaunchMain)
-                                                .expect("Failed to send launch message");
-                                        }
-                                        Vec::from(include_str!("blank.html"))
-                                    }
-                                    "kill_cosmic" => {
-                                        if let Some(ref mut sender) = context.launcher {
-                                            sender
-                                                .send(ProcessRequest::KillMain)
-                                                .expect("Failed to send kill message");
-                                        }
-                                        Vec::from(include_str!("blank.html"))
-                                    }
-                                    "launch_stage_editor" => {
-                                        if let Some(ref mut sender) = context.launcher {
-                                            sender
-                                                .send(ProcessRequest::LaunchStageEditor)
-                                                .expect("Failed to send kill message");
-                                        }
-                                        Vec::from(include_str!("blank.html"))
-                                    }
-                                    "kill_stage_editor" => {
-                                        if let Some(ref mut sender) = context.launcher {
-                                            sender
-                                                .send(ProcessRequest::KillStageEditor)
-                                                .expect("Failed to send kill message");
-                                        }
-                                        Vec::from(include_str!("blank.html"))
-                                    }
-                                    "exit" => {
-                                        std::process::exit(0);
-                                    }
-                                    _ => Vec::from(include_str!("blank.html")),
-                                }
-                            } else {
-                                Vec::from(include_str!("blank.html"))
-                            }
-                        }
-                        "./items" | "./parts" | "./kills" | "./labo" | "./use" | "./gacha"
-                        | "./dungeon_clear" | "./burst" | "./mission" | "./shuttle" => {
-                            let (last, paths) = search_latest_log_file(chat_dir_path);
-                            let statics_address = StaticsAddress::from_url(uri.as_str()).unwrap();
-                            let need_to_load = context.general_statics[statics_address.as_uint()]
-                                .query_cache(&paths);
-                            //更新が必要なものをリストアップ
-
-                            use std::sync::Arc;
-                            let (tx, rx) = std::sync::mpsc::channel();
-                            let tx = Arc::new(Mutex::new(tx));
-                            let ntl = need_to_load.clone();
-                            #[cfg(debug_assertions)]
-                            println!("{:#?}", ntl);
-
-                            for path in ntl {
-                                use std::thread;
-                                let tx = tx.clone();
-                                let _uri = uri.clone();
-                                thread::spawn(move || match statics_address {
-                                    StaticsAddress::Item => {
-                                        let texts = read_from_file(path);
-                                        let data = engine_item_get(&texts.1, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::ItemUse => {
-                                        let texts = read_from_file(path);
-                                        let data = engine_item_use(&texts.1, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::Parts => {
-                                        let texts = read_from_file(path);
-                                        let data = engine_get_part(&texts.1, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::Kill => {
-                                        let texts = read_from_file(path);
-                                        let data = engine_kill_self(&texts.1, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::Burst
-                                    | StaticsAddress::Mission
-                                    | StaticsAddress::DungeonClear
-                                    | StaticsAddress::Shuttle => {
-                                        let texts = read_from_file3(path);
-                                        let data = engine_tsv_match(
-                                            &texts,
-                                            &DICTIONARIES
-                                                [statics_address.as_dictionary_index().unwrap()],
-                                            0,
-                                        );
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::Lab => {
-                                        let texts = read_from_file2(path);
-                                        let data = engine_labo(&texts, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    StaticsAddress::Gacha => {
-                                        let texts = read_from_file(path);
-                                        let data = engine_gacha(&texts.1, 0);
-                                        tx.lock().unwrap().send(data).unwrap();
-                                    }
-                                    _ => {}
-                                });
-                            }
-                            if !need_to_load.is_empty() {
-                                for (id, rcv) in rx.iter().enumerate() {
-                                    #[cfg(debug_assertions)]
-                                    println!("id: {}, len: {}", id + 1, need_to_load.len());
-                                    if id + 1 == need_to_load.len() {
-                                        break;
-                                    }
-                                    context.general_statics[statics_address.as_uint()]
-                                        .update_statics(rcv);
-                                }
-                            }
-                            let items =
-                                context.general_statics[statics_address.as_uint()].get_statics();
-                            let updating = match statics_address {
-                                StaticsAddress::Item => {
-                                    let texts = read_from_file(last);
-                                    engine_item_get(&texts.1, 0)
-                                }
-                                StaticsAddress::ItemUse => {
-                                    let texts = read_from_file(last);
-                                    engine_item_use(&texts.1, 0)
-                                }
-                                StaticsAddress::Parts => {
-                                    let texts = read_from_file(last);
-                                    engine_get_part(&texts.1, 0)
-                                }
-                                StaticsAddress::Kill => {
-                                    let texts = read_from_file(last);
-                                    engine_kill_self(&texts.1, 0)
-                                }
-                                StaticsAddress::Burst
-                                | StaticsAddress::Mission
-                                | StaticsAddress::DungeonClear
-                                | StaticsAddress::Shuttle => {
-                                    let texts = read_from_file3(last);
-                                    engine_tsv_match(
-                                        &texts,
-                                        &DICTIONARIES
-                                            [statics_address.as_dictionary_index().unwrap()],
-                                        0,
-                                    )
-                                }
-                                StaticsAddress::Lab => {
-                                    let texts = read_from_file2(last);
-                                    engine_labo(&texts, 0)
-                                }
-                                StaticsAddress::Gacha => {
-                                    let texts = read_from_file(last);
-                                    engine_gacha(&texts.1, 0)
-                                }
-                                _ => {
-                                    unreachable!()
-                                }
-                            };
-
-                            //ITEMSとLDSを統合して出力
-                            let set = connect_hashmap(items, updating);
-                            let mut vector = hashmap_to_vec(&set);
-                            sort(&mut vector, SortTarget::NAME, true);
-                            let ctx = GeneralStaticsTemplate {
-                                name: statics_address.to_string(),
-                                statics: vector,
-                            };
-                            ctx.render_once().unwrap().into_bytes()
-                        }
-                        "./dungeon" => {
-                            let (last, _paths) = search_latest_log_file(chat_dir_path);
-                            let texts = read_from_file(&last);
-                            //if updating file changed reset state machine
-                            if context.current_updating_file != last {
-                                context.dungeon_state_machine = DungeonStateMachine::init(
-                                    texts.1.clone(),
-                                    texts.0.clone(),
-                                    texts.1.len(),
-                                );
-                            }
-                            //supply text
-                            let current_texts =
-                                context.dungeon_state_machine.get_current_text_len();
-                            context.dungeon_state_machine.supply_text((
-                                &texts.0[current_texts..texts.1.len()],
-                                &texts.1[current_texts..texts.1.len()],
-                            ));
-
-                            context.dungeon_state_machine.state_change();
-                            let state = context.dungeon_state_machine.inspect_state();
-
-                            println!("current state {:?}", state);
-                            context.current_updating_file = last.clone();
-                            if let Some(statics) = context.dungeon_state_machine.statics() {
-                                let ctx = DungeonStaticsTemplate {
-                                    lap_time: statics.lap_time,
-                                    dungeon_name: "ダンジョン内カウント".to_string(),
-                                    set_of_statics: vec![
-                                        GeneralStaticsTemplate {
-                                            name: "アイテム取得".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[0]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "パーツ取得".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[2]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "アイテム使用".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[1]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "キル".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[3]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "報酬".to_string(),
-                                            statics: {
-                                                let mut vector = hashmap_to_vec(&statics.rewards);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "報酬売却".to_string(),
-                                            statics: {
-                                                let mut vector = hashmap_to_vec(&statics.sells);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                    ],
-                                };
-                                /*
-                                let ctx = InFloorStaticsTemplate {
-                                    name: "ダンジョン内カウント".to_string(),
-                                    set_of_statics: vec![
-                                        GeneralStaticsTemplate {
-                                            name: "アイテム取得".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[0]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "パーツ取得".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[2]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "アイテム使用".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[1]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "キル".to_string(),
-                                            statics: {
-                                                let mut vector =
-                                                    hashmap_to_vec(&statics.statics[3]);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "報酬".to_string(),
-                                            statics: {
-                                                let mut vector = hashmap_to_vec(&statics.rewards);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                        GeneralStaticsTemplate {
-                                            name: "報酬売却".to_string(),
-                                            statics: {
-                                                let mut vector = hashmap_to_vec(&statics.sells);
-                                                sort(&mut vector, SortTarget::NAME, true);
-                                                vector
-                                            },
-                                        },
-                                    ],
-                                };*/
-                                let table = ctx.render_once().unwrap();
-                                let bytes = table.into_bytes();
-                                //out put log
-                                if let Some(range) =
-                                    context.dungeon_state_machine.query_dungeon_range()
-                                {
-                                    let path = Path::new(&last);
-                                    let stem = path.file_stem().unwrap();
-                                    let stem = stem.to_str().unwrap();
-
-                                    if !Path::new("./dungeon_statics").exists() {
-                                        std::fs::create_dir("./dungeon_statics").unwrap();
-                                    }
-                                    let file_name = format!(
-                                        "./dungeon_statics/{}@{}_{}.html",
-                                        stem, range.start, range.end
-                                    );
-                                    let mut file = std::fs::File::create(file_name).unwrap();
-
-                                    file.write_all(&bytes).unwrap();
-                                    file.flush().unwrap();
-                                }
-
-                                bytes
-                            } else {
-                                Vec::from(include_str!("not_entered.html"))
-                            }
-                        }
-
-                        "./floor" => {
-                            let (last, _texts) = search_latest_log_file(chat_dir_path);
-                            let texts = read_from_file(last);
-                            let from = search_floor_last(&texts.1, 0);
-                            match from {
-                                None => Vec::from(include_str!("not_entered.html")),
-                                Some(from) => {
-                                    let mut lds = Vec::new();
-                                    lds.push(engine_item_get(&texts.1, from));
-                                    lds.push(engine_get_part(&texts.1, from));
-                                    lds.push(engine_item_use(&texts.1, from));
-                                    lds.push(engine_kill_self(&texts.1, from));
-
-                                    let ctx = InFloorStaticsTemplate {
-                                        name: "フロア内カウント".to_string(),
-                                        set_of_statics: vec![
-                                            GeneralStaticsTemplate {
-                                                name: "アイテム取得".to_string(),
-                                                statics: {
-                                                    let mut vector = hashmap_to_vec(&lds[0]);
-                                                    sort(&mut vector, SortTarget::NAME, true);
-                                                    vector
-                                                },
-                                            },
-                                            GeneralStaticsTemplate {
-                                                name: "パーツ取得".to_string(),
-                                                statics: {
-                                                    let mut vector = hashmap_to_vec(&lds[1]);
-                                                    sort(&mut vector, SortTarget::NAME, true);
-                                                    vector
-                                                },
-                                            },
-                                            GeneralStaticsTemplate {
-                                                name: "アイテム使用".to_string(),
-                                                statics: {
-                                                    let mut vector = hashmap_to_vec(&lds[2]);
-                                                    sort(&mut vector, SortTarget::NAME, true);
-                                                    vector
-                                                },
-                                            },
-                                            GeneralStaticsTemplate {
-                                                name: "キル".to_string(),
-                                                statics: {
-                                                    let mut vector = hashmap_to_vec(&lds[3]);
-                                                    sort(&mut vector, SortTarget::NAME, true);
-                                                    vector
-                                                },
-                                            },
-                                        ],
-                                    };
-                                    let table = ctx.render_once().unwrap();
-                                    table.into_bytes()
-                                }
-                            }
-                        }
-
-                        _ => Vec::from(include_str!("not_found.html")),
-                    }
-                }
-            }
-        };
-        let mut page = Vec::with_capacity(4096);
-        page.append(&mut header);
-        page.append(&mut payload);
-        page
-    } else {
-        //設定ファイルが読めないとき
-        let mut header = Vec::from("HTTP/1.1 200 OK\r\n\r\n");
-        let mut buffer = Vec::with_capacity(512);
-
-        match get_path_from_launcher() {
-            Ok(setting) => {
-                //write setting
-                #[cfg(debug_assertions)]
-                println!("Setting generated");
-                let config_file_content = toml::to_string(&setting).unwrap();
-                std::fs::write("./Settings.toml", config_file_content).unwrap();
-                //config.replace(setting);
-                let mut old_position = Some(setting);
-                std::mem::swap(&mut context.config, &mut old_position);
-                #[cfg(debug_assertions)]
-                println!("{:#?}", old_position);
-                #[cfg(debug_assertions)]
-                println!("{:#?}", context.config);
-                let mut config_not_found_page = File::open("config_not_found.html").unwrap();
-                config_not_found_page.read_to_end(&mut buffer).unwrap();
-            }
-            Err(_) => {
-                let mut please_start_launcher_page =
-                    File::open("please_start_launcher.html").unwrap();
-                please_start_launcher_page.read_to_end(&mut buffer).unwrap();
-            }
+    };
+    if !matches!(command, Command::Serve { .. }) {
+        attach_console();
+    }
+    match command {
+        Command::Serve { no_browser } => serve(no_browser).await,
+        Command::Stats {
+            counter,
+            range,
+            format,
+        } => print_command(|context| {
+            let config = context
+                .config()
+                .ok_or_else(|| "Settings.toml is not loaded".to_string())?;
+            cli::stats(context, &config, &counter, &range, format)
+        }),
+        Command::Runs { query, format } => print_command(|context| {
+            let config = context
+                .config()
+                .ok_or_else(|| "Settings.toml is not loaded".to_string())?;
+            cli::runs(context, &config, &query, format)
+        }),
+        Command::Replay { path } => replay_command(&path),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
         }
-
-        header.append(&mut buffer);
-
-        header
     }
 }
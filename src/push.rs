@@ -0,0 +1,137 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::engines::InnerStatics;
+use crate::server::update_live;
+use crate::setting::Setting;
+use crate::statics_address::StaticsAddress;
+use crate::Context;
+
+/// Server-Sent Eventsの接続先
+pub struct Broadcaster {
+    clients: Mutex<Vec<UnboundedSender<Bytes>>>,
+}
+
+impl Broadcaster {
+    pub fn new() -> Self {
+        Self {
+            clients: Mutex::new(Vec::new()),
+        }
+    }
+    fn new_client(&self) -> UnboundedReceiver<Bytes> {
+        let (tx, rx) = unbounded();
+        tx.unbounded_send(Bytes::from_static(b"retry: 3000\n\n"))
+            .ok();
+        self.clients.lock().unwrap().push(tx);
+        rx
+    }
+    fn broadcast(&self, message: Bytes) {
+        //切断されたクライアントは取り除く
+        self.clients
+            .lock()
+            .unwrap()
+            .retain(|client| client.unbounded_send(message.clone()).is_ok());
+    }
+    pub fn send<T: Serialize>(&self, event: &str, data: &T) {
+        let message = format!(
+            "event: {}\ndata: {}\n\n",
+            event,
+            serde_json::to_string(data).unwrap()
+        );
+        self.broadcast(Bytes::from(message));
+    }
+    fn ping(&self) {
+        self.broadcast(Bytes::from_static(b": ping\n\n"));
+    }
+}
+
+pub async fn events(context: web::Data<Context>) -> HttpResponse {
+    let rx = context.broadcaster.new_client();
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(rx.map(Ok::<_, actix_web::Error>))
+}
+
+/// カウンタごとの差分
+#[derive(Serialize)]
+struct Delta {
+    counter: &'static str,
+    deltas: InnerStatics,
+}
+
+/// 最新のログを読み進めて,伸びたときだけ差分を通知する
+struct LogFollower {
+    generation: Option<usize>,
+    idle: usize,
+}
+
+impl LogFollower {
+    fn new() -> Self {
+        Self {
+            generation: None,
+            idle: 0,
+        }
+    }
+    fn poll(&mut self, context: &Context, config: &Setting) {
+        let files = context.chat_files(config);
+        let mut live = update_live(context, &files);
+        let deltas = live.take_deltas();
+        let generation = live.generation();
+        drop(live);
+        //新しいログファイルに切り替わったか,切り詰められた
+        let previous = self.generation.replace(generation);
+        if previous != Some(generation) {
+            if previous.is_some() {
+                context.broadcaster.send("reload", &files.active);
+            }
+            return;
+        }
+        let deltas = match deltas {
+            Some(deltas) => deltas,
+            None => {
+                self.idle += 1;
+                if self.idle % 15 == 0 {
+                    context.broadcaster.ping();
+                }
+                return;
+            }
+        };
+        self.idle = 0;
+        for statics_address in StaticsAddress::COUNTERS.iter() {
+            let deltas = &deltas[statics_address.as_uint()];
+            if !deltas.is_empty() {
+                let counter = statics_address.to_url().unwrap().trim_start_matches('/');
+                context.broadcaster.send(
+                    "delta",
+                    &Delta {
+                        counter,
+                        deltas: deltas.clone(),
+                    },
+                );
+            }
+        }
+        //ダンジョンやフロアのページは再取得する
+        context.broadcaster.send("log", &files.active);
+    }
+}
+
+/// 最新のログを監視するスレッドを立ち上げる
+pub fn follow_log(context: web::Data<Context>) {
+    std::thread::spawn(move || {
+        let mut follower = LogFollower::new();
+        loop {
+            std::thread::sleep(Duration::from_secs(1));
+            if let Some(config) = context.config() {
+                follower.poll(&context, &config);
+            }
+        }
+    });
+}
@@ -0,0 +1,132 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::engines::InnerStatics;
+use crate::utils::format_time;
+
+/// 新しい当たりから表示する件数
+pub const RECENT_LEN: usize = 50;
+
+/// 誰かが [X] をガチャセンターで当てました！
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RareWin {
+    pub time: String,
+    pub name: String,
+}
+
+/// アイテムごとの当たりの回数と最後に当たった時刻
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RareItem {
+    pub name: String,
+    pub count: isize,
+    pub last: String,
+    /// サーバー全体の当たりに占める割合
+    pub share: f64,
+    /// 自分が当てた回数
+    pub own: isize,
+    /// 自分の当たりに占める割合
+    pub own_share: Option<f64>,
+}
+
+/// サーバー全体のレアガチャの当たり
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RareFeed {
+    pub total: isize,
+    pub own_total: isize,
+    /// 回数の多い順
+    pub items: Vec<RareItem>,
+    /// 新しい順
+    pub recent: Vec<RareWin>,
+}
+
+impl RareFeed {
+    /// winsは時刻順,ownは自分のガチャの統計
+    pub fn new(wins: &[(NaiveDateTime, String)], own: &InnerStatics) -> Self {
+        let mut items: Vec<RareItem> = Vec::new();
+        for (time, name) in wins {
+            match items.iter_mut().find(|item| item.name == *name) {
+                Some(item) => {
+                    item.count += 1;
+                    item.last = format_time(Some(*time));
+                }
+                None => items.push(RareItem {
+                    name: name.clone(),
+                    count: 1,
+                    last: format_time(Some(*time)),
+                    share: 0.0,
+                    own: own.get(name).copied().unwrap_or(0),
+                    own_share: None,
+                }),
+            }
+        }
+        let total = wins.len() as isize;
+        let own_total = own.values().sum();
+        for item in &mut items {
+            item.share = item.count as f64 / total as f64;
+            if own_total > 0 {
+                item.own_share = Some(item.own as f64 / own_total as f64);
+            }
+        }
+        items.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+        let recent = wins
+            .iter()
+            .rev()
+            .take(RECENT_LEN)
+            .map(|(time, name)| RareWin {
+                time: format_time(Some(*time)),
+                name: name.clone(),
+            })
+            .collect();
+        Self {
+            total,
+            own_total,
+            items,
+            recent,
+        }
+    }
+}
+
+/// 割合を百分率で表示する
+pub fn format_share(share: Option<f64>) -> String {
+    share
+        .map(|share| format!("{:.2}%", share * 100.0))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod rare_feed_test {
+    use chrono::NaiveDate;
+
+    use crate::engines::InnerStatics;
+    use crate::rare_feed::{format_share, RareFeed};
+
+    #[test]
+    fn test_rare_feed() {
+        let time = |minute| {
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, minute, 0)
+                .unwrap()
+        };
+        let wins = vec![
+            (time(0), "レアパーツ".to_string()),
+            (time(1), "レアスーツ".to_string()),
+            (time(2), "レアパーツ".to_string()),
+            (time(3), "レアパーツ".to_string()),
+        ];
+        let mut own = InnerStatics::new();
+        own.insert("レアパーツ".to_string(), 1);
+        own.insert("ENパック2000".to_string(), 3);
+        let feed = RareFeed::new(&wins, &own);
+        assert_eq!(feed.total, 4);
+        assert_eq!(feed.own_total, 4);
+        assert_eq!(feed.items[0].name, "レアパーツ");
+        assert_eq!(feed.items[0].count, 3);
+        assert_eq!(feed.items[0].last, "2021-01-01 00:03:00");
+        assert_eq!(format_share(Some(feed.items[0].share)), "75.00%");
+        assert_eq!(feed.items[0].own_share, Some(0.25));
+        assert_eq!(feed.items[1].own, 0);
+        assert_eq!(feed.recent[0].time, "2021-01-01 00:03:00");
+        assert_eq!(format_share(None), "");
+    }
+}
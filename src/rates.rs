@@ -0,0 +1,135 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::engines::InnerStatics;
+use crate::session::SessionSummary;
+use crate::time_range::TimeRange;
+
+/// 個数を1時間あたりと1周あたりに直すための期間と周回数
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Rates {
+    /// ログが書かれていた時間(秒)
+    pub seconds: i64,
+    /// 期間内に始まった周回の数
+    pub runs: usize,
+    /// 周回の中で数えた個数
+    /// 1周あたりはこれを周回数で割る
+    pub in_runs: InnerStatics,
+}
+
+impl Rates {
+    /// 期間に含まれるセッションの長さと周回数を数える
+    /// count_runsはセッションの最初と最後の時刻の間に始まった期間内の周回の数を返す
+    pub fn in_range<F>(range: &TimeRange, summaries: &[SessionSummary], count_runs: F) -> Self
+    where
+        F: Fn(NaiveDateTime, NaiveDateTime) -> usize,
+    {
+        let mut rates = Self::default();
+        for summary in summaries {
+            if !range.includes_file(&summary.file) {
+                continue;
+            }
+            let (start, end) = match (summary.start, summary.end) {
+                (Some(start), Some(end)) => (start, end),
+                _ => continue,
+            };
+            let from = range.from.map_or(start, |from| from.max(start));
+            let to = range.to.map_or(end, |to| to.min(end));
+            if from < to {
+                rates.seconds += (to - from).num_seconds();
+            }
+            rates.runs += count_runs(start, end);
+        }
+        rates
+    }
+    /// ダンジョン1周の中の時間あたり
+    pub fn in_run(lap_time: Option<chrono::Duration>) -> Self {
+        Self {
+            seconds: lap_time.map_or(0, |lap_time| lap_time.num_seconds()),
+            ..Self::default()
+        }
+    }
+    pub fn per_hour(&self, count: isize) -> Option<f64> {
+        if self.seconds <= 0 {
+            return None;
+        }
+        Some(count as f64 * 3600.0 / self.seconds as f64)
+    }
+    /// 周回の外で得たものは数えない
+    pub fn per_run(&self, name: &str) -> Option<f64> {
+        if self.runs == 0 {
+            return None;
+        }
+        Some(self.in_runs.get(name).copied().unwrap_or(0) as f64 / self.runs as f64)
+    }
+    /// 表示用 小数1桁
+    pub fn per_hour_text(&self, count: isize) -> String {
+        format_rate(self.per_hour(count))
+    }
+    pub fn per_run_text(&self, name: &str) -> String {
+        format_rate(self.per_run(name))
+    }
+}
+
+fn format_rate(rate: Option<f64>) -> String {
+    rate.map(|rate| format!("{:.1}", rate)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod rates_test {
+    use chrono::NaiveDate;
+
+    use crate::rates::Rates;
+    use crate::session::SessionSummary;
+    use crate::time_range::TimeRange;
+
+    #[test]
+    fn test_in_range() {
+        let time = |hour| {
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(hour, 0, 0)
+                .unwrap()
+        };
+        let session = |path: &str, start, end| {
+            let mut summary = SessionSummary::new(path, false);
+            summary.start = Some(time(start));
+            summary.end = Some(time(end));
+            summary
+        };
+        let summaries = vec![session("a.txt", 0, 2), session("b.txt", 4, 8)];
+        //周回の開始時刻
+        let runs = [time(1), time(5), time(6)];
+        let count_runs = |range: &TimeRange| {
+            let range = range.clone();
+            move |start, end| {
+                runs.iter()
+                    .filter(|run| start <= **run && **run <= end && range.contains(**run))
+                    .count()
+            }
+        };
+        let range = TimeRange::default();
+        let mut rates = Rates::in_range(&range, &summaries, count_runs(&range));
+        assert_eq!((rates.seconds, rates.runs), (6 * 3600, 3));
+        assert_eq!(rates.per_hour(60), Some(10.0));
+        rates.in_runs.insert("[リペアパック]".to_string(), 10);
+        assert_eq!(rates.per_run_text("[リペアパック]"), "3.3");
+        //周回の外でしか得ていない
+        assert_eq!(rates.per_run("[ソード]"), Some(0.0));
+
+        let range = TimeRange {
+            from: Some(time(5)),
+            ..TimeRange::default()
+        };
+        let rates = Rates::in_range(&range, &summaries, count_runs(&range));
+        assert_eq!((rates.seconds, rates.runs), (3 * 3600, 2));
+
+        let range = TimeRange {
+            session: Some("a.txt".to_string()),
+            ..TimeRange::default()
+        };
+        let rates = Rates::in_range(&range, &summaries, count_runs(&range));
+        assert_eq!((rates.seconds, rates.runs), (2 * 3600, 1));
+        assert_eq!(Rates::in_run(None).per_hour_text(1), "");
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::dungeon_state_machine::{DungeonRun, DungeonState, DungeonStateMachine};
+use crate::log_event::TimedEvent;
+use crate::utils::{format_seconds, format_time};
+
+/// 状態の遷移
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    /// 遷移したときに見ていたレコードの番号
+    pub line: usize,
+    pub time: String,
+    pub from: DungeonState,
+    pub to: DungeonState,
+}
+
+/// 過去のログを状態機械に流した結果
+#[derive(Debug)]
+pub struct Replay {
+    pub transitions: Vec<Transition>,
+    pub runs: Vec<DungeonRun>,
+    /// ログの末尾での状態
+    pub state: DungeonState,
+}
+
+/// ログの時刻だけを使って状態機械を最後まで進める
+/// 追記を読むときと同じくfeedで渡す
+pub fn replay(events: &[TimedEvent]) -> Replay {
+    let mut state_machine = DungeonStateMachine::replay(Vec::new());
+    let mut transitions = Vec::new();
+    state_machine.feed(events, |from, to, event| {
+        transitions.push(Transition {
+            line: event.map_or(0, |event| event.line),
+            time: format_time(event.map(|event| event.time)),
+            from,
+            to,
+        })
+    });
+    Replay {
+        transitions,
+        runs: state_machine.take_runs(),
+        state: *state_machine.inspect_state(),
+    }
+}
+
+impl Replay {
+    /// 遷移と周回を1行ずつ書き出す
+    pub fn print(&self) {
+        print!("{}", self);
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for transition in &self.transitions {
+            writeln!(
+                f,
+                "{}\t{}\t{:?} -> {:?}",
+                transition.line, transition.time, transition.from, transition.to
+            )?;
+        }
+        for run in &self.runs {
+            writeln!(
+                f,
+                "run\t{}\t{}\t{}\t{}\t{}",
+                run.dungeon.as_deref().unwrap_or("不明"),
+                format_time(Some(run.start)),
+                format_time(run.clear),
+                format_time(Some(run.exit)),
+                run.lap_time
+                    .map(|lap_time| format_seconds(lap_time.num_seconds()))
+                    .unwrap_or_default()
+            )?;
+            if let Some(abort) = run.abort {
+                writeln!(f, "abort\t{}", abort.as_str())?;
+            }
+        }
+        writeln!(f, "state\t{:?}", self.state)
+    }
+}
+
+#[cfg(test)]
+mod replay_test {
+    use crate::dungeon_state_machine::{AbortReason, DungeonState, DungeonStateMachine};
+    use crate::log_event::EventParser;
+    use crate::replay::replay;
+
+    #[test]
+    fn test_replay_uses_log_time() {
+        let text = "2021-01-01\t00:00:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:05:00\t[INFO]\tダンジョン成功報酬\r\n\
+                    2021-01-01\t00:05:01\t[INFO]\t報酬－ ENパック2000 x 1\r\n\
+                    2021-01-01\t00:05:02\t[INFO]\t報酬売却－ ENパック2000 x 1\r\n\
+                    2021-01-01\t00:05:03\t[INFO]\t報酬売却計 100\r\n";
+        let result = replay(&EventParser::new().parse(text));
+        let states: Vec<DungeonState> = result
+            .transitions
+            .iter()
+            .map(|transition| transition.to)
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                DungeonState::Dungeon,
+                DungeonState::Clear,
+                DungeonState::Reward,
+                DungeonState::Sell,
+                DungeonState::OutOfDungeon
+            ]
+        );
+        assert_eq!(result.transitions[1].line, 1);
+        assert_eq!(result.transitions[1].time, "2021-01-01 00:05:00");
+        assert_eq!(result.runs.len(), 1);
+        assert_eq!(result.runs[0].lap_time, Some(chrono::Duration::minutes(5)));
+        //売却の合計で周回を終える
+        assert_eq!(
+            result.runs[0].exit,
+            result.runs[0].start + chrono::Duration::seconds(303)
+        );
+        assert_eq!(result.state, DungeonState::OutOfDungeon);
+    }
+
+    #[test]
+    fn test_replay_abort() {
+        let text = "2021-01-01\t00:00:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:03:00\t[INFO]\t[エスケープゲート]を使用した！\r\n\
+                    2021-01-01\t00:10:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:11:00\t[INFO]\t[ポルドジャンプ]を使用した！\r\n\
+                    2021-01-01\t00:20:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:21:00\t[INFO]\tホネボンを撃破した\r\n\
+                    2021-01-01\t01:00:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t01:05:00\t[INFO]\tダンジョン成功報酬\r\n";
+        let result = replay(&EventParser::new().parse(text));
+        let aborts: Vec<Option<AbortReason>> = result.runs.iter().map(|run| run.abort).collect();
+        assert_eq!(
+            aborts,
+            vec![
+                Some(AbortReason::EscapeGate),
+                Some(AbortReason::Jump),
+                Some(AbortReason::Inactive),
+            ]
+        );
+        //中断した周回はラップタイムを持たない
+        assert!(result.runs.iter().all(|run| run.lap_time.is_none()));
+        assert_eq!(
+            result.runs[0].exit,
+            result.runs[0].start + chrono::Duration::minutes(3)
+        );
+        //放置は最後のログの時刻で終わる
+        assert_eq!(
+            result.runs[2].exit,
+            result.runs[2].start + chrono::Duration::minutes(1)
+        );
+        assert_eq!(result.transitions[1].to, DungeonState::Aborted);
+    }
+
+    #[test]
+    fn test_feed_batch_and_one_by_one() {
+        //クリアの後,待ち時間を過ぎてから次のフロアゲート
+        let text = "2021-01-01\t00:00:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:05:00\t[INFO]\tダンジョン成功報酬\r\n\
+                    2021-01-01\t00:10:00\t[INFO]\tAがフロアゲートを起動した！\r\n\
+                    2021-01-01\t00:15:00\t[INFO]\tダンジョン成功報酬\r\n\
+                    2021-01-01\t00:15:01\t[INFO]\t報酬－ ENパック2000 x 1\r\n\
+                    2021-01-01\t00:15:02\t[INFO]\t報酬売却－ ENパック2000 x 1\r\n\
+                    2021-01-01\t00:15:03\t[INFO]\t報酬売却計 100\r\n";
+        let events = EventParser::new().parse(text);
+        let mut batch = DungeonStateMachine::replay(Vec::new());
+        batch.feed(&events, |_, _, _| {});
+        let mut one_by_one = DungeonStateMachine::replay(Vec::new());
+        for event in events.chunks(1) {
+            one_by_one.feed(event, |_, _, _| {});
+        }
+        let (batch, one_by_one) = (batch.take_runs(), one_by_one.take_runs());
+        assert_eq!(format!("{:?}", batch), format!("{:?}", one_by_one));
+        assert_eq!(batch.len(), 2);
+        assert_eq!(
+            batch[0].exit,
+            batch[0].clear.unwrap() + chrono::Duration::minutes(2)
+        );
+    }
+}
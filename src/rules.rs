@@ -0,0 +1,186 @@
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// ダンジョンの状態機械が使う時間とログの文言
+/// dungeon_rules.tomlから読み込む
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DungeonRules {
+    /// クリアしてから報酬が出るまで待つ時間(秒)
+    pub reward_timeout: i64,
+    /// フロアゲートの起動がこれより新しければ現在時刻で進める(秒)
+    pub sync_threshold: i64,
+    /// ダンジョン内でログがこれより途切れたら中断とする(秒)
+    pub inactivity_timeout: i64,
+    /// ダンジョンクリア
+    pub clear: String,
+    /// 報酬－ ENパック2000 x 1
+    pub reward: String,
+    /// 報酬売却－ ENパック2000 x 1
+    pub sell: String,
+    /// 報酬売却計
+    pub sell_total: String,
+}
+
+impl Default for DungeonRules {
+    fn default() -> Self {
+        Self {
+            reward_timeout: 120,
+            sync_threshold: 6,
+            inactivity_timeout: 600,
+            clear: "ダンジョン成功報酬".to_string(),
+            reward: "報酬－".to_string(),
+            sell: "報酬売却－".to_string(),
+            sell_total: "報酬売却計".to_string(),
+        }
+    }
+}
+
+impl DungeonRules {
+    /// ファイルがなければ既定の値
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        toml::from_str(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+    /// 状態機械が全ての状態に遷移できるか確かめて正規表現を作る
+    pub fn compile(self) -> Result<Rules, String> {
+        if self.reward_timeout <= 0 {
+            return Err("reward_timeout must be positive".to_string());
+        }
+        if self.sync_threshold < 0 || self.sync_threshold >= self.reward_timeout {
+            return Err("sync_threshold must be between 0 and reward_timeout".to_string());
+        }
+        if self.inactivity_timeout <= 0 {
+            return Err("inactivity_timeout must be positive".to_string());
+        }
+        //分類はこの順に試すので,後の文言が前の文言を含むと後の遷移が起きない
+        let triggers = [
+            ("sell_total", &self.sell_total),
+            ("sell", &self.sell),
+            ("reward", &self.reward),
+            ("clear", &self.clear),
+        ];
+        for (index, (name, trigger)) in triggers.iter().enumerate() {
+            if trigger.is_empty() {
+                return Err(format!("{} is empty", name));
+            }
+            for (earlier, earlier_trigger) in &triggers[..index] {
+                if trigger.contains(earlier_trigger.as_str()) {
+                    return Err(format!("{} contains {}", name, earlier));
+                }
+            }
+        }
+        let item = |trigger: &str| {
+            Regex::new(&format!(
+                r"{} (?P<name>.+) x (?P<N>\d+)",
+                regex::escape(trigger)
+            ))
+        };
+        Ok(Rules {
+            reward: item(&self.reward).map_err(|error| error.to_string())?,
+            sell: item(&self.sell).map_err(|error| error.to_string())?,
+            sell_total: Regex::new(&format!(
+                r"{}\D*(?P<N>[\d,]+)",
+                regex::escape(&self.sell_total)
+            ))
+            .map_err(|error| error.to_string())?,
+            dungeon: self,
+        })
+    }
+}
+
+/// 検証済みのルール
+#[derive(Debug)]
+pub struct Rules {
+    pub dungeon: DungeonRules,
+    pub(crate) reward: Regex,
+    pub(crate) sell: Regex,
+    pub(crate) sell_total: Regex,
+}
+
+impl Rules {
+    pub fn reward_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.dungeon.reward_timeout)
+    }
+    pub fn sync_threshold(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.dungeon.sync_threshold)
+    }
+    pub fn inactivity_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.dungeon.inactivity_timeout)
+    }
+    /// 文言や時間が変わると変わる値
+    /// 保存済みのイベントを分類し直すかの判定に使う
+    /// ツールチェーンによらないようにTOMLそのものを使う
+    pub fn version(&self) -> String {
+        toml::to_string(&self.dungeon).unwrap_or_default()
+    }
+}
+
+lazy_static! {
+    static ref RULES: RwLock<Arc<Rules>> =
+        RwLock::new(Arc::new(DungeonRules::default().compile().unwrap()));
+}
+
+/// 今のルール
+pub fn current() -> Arc<Rules> {
+    RULES.read().unwrap().clone()
+}
+
+/// 設定からルールを読み込んで入れ替える
+/// 読めなければ今のルールのまま
+pub fn install(path: &str) {
+    match DungeonRules::load(path).and_then(DungeonRules::compile) {
+        Ok(rules) => *RULES.write().unwrap() = Arc::new(rules),
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+#[cfg(test)]
+mod rules_test {
+    use crate::log_event::{classify_info_with, LogEvent};
+    use crate::rules::DungeonRules;
+
+    #[test]
+    fn test_compile() {
+        let rules = DungeonRules {
+            reward: "Reward:".to_string(),
+            ..DungeonRules::default()
+        }
+        .compile()
+        .unwrap();
+        assert_eq!(
+            classify_info_with("Reward: EXP x 10", &rules),
+            Some(LogEvent::DungeonReward {
+                name: "EXP".to_string(),
+                qty: 10
+            })
+        );
+        //売却の行が報酬売却計に分類されてしまう
+        let error = DungeonRules {
+            sell: "報酬売却計－".to_string(),
+            ..DungeonRules::default()
+        }
+        .compile()
+        .unwrap_err();
+        assert_eq!(error, "sell contains sell_total");
+        //保存する版はルールの中身そのもの
+        assert!(rules.version().contains("reward = \"Reward:\""));
+        assert_ne!(
+            rules.version(),
+            DungeonRules::default().compile().unwrap().version()
+        );
+        assert!(DungeonRules {
+            sync_threshold: 200,
+            ..DungeonRules::default()
+        }
+        .compile()
+        .is_err());
+    }
+}
@@ -0,0 +1,1318 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::MutexGuard;
+
+use actix_files::Files;
+use actix_web::dev::{fn_service, ServiceRequest, ServiceResponse};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use sailfish::TemplateOnce;
+use serde::Deserialize;
+
+use crate::api;
+use crate::dungeon_state_machine::{DungeonOutPut, DungeonRun, DungeonState, FloorSplit};
+use crate::engines::{
+    add_to_table, engine_get_part, engine_item_get, engine_item_use, engine_kill_self,
+    search_floor_last, DungeonRewardElement, InnerStatics, RewardIncome,
+};
+use crate::gacha::{engine_gacha_pulls, GachaAnalytics};
+use crate::ingest;
+use crate::lab::{engine_synthesis, LabLedger};
+use crate::live_log::LiveLog;
+use crate::mesa_inject;
+use crate::process_manager::{update, ProcessRequest};
+use crate::push;
+use crate::rare_feed::{format_share, RareFeed};
+use crate::rates::Rates;
+use crate::replay::{self, Replay};
+use crate::rules;
+use crate::session::{SessionSummary, SESSION_COUNTERS};
+use crate::setting::{get_path_from_launcher, Setting};
+use crate::statics_address::StaticsAddress;
+use crate::store::{ClearRate, LapRecord};
+use crate::time_range::{RangeQuery, TimeRange};
+use crate::travel::{engine_travel, TravelReport};
+use crate::utils::{
+    connect_hashmap, format_seconds, format_time, hashmap_to_vec, read_events, sort, SortTarget,
+};
+use crate::watcher::ChatFiles;
+use crate::Context;
+
+#[derive(TemplateOnce)]
+#[template(path = "general.stpl")]
+struct GeneralStaticsTemplate {
+    name: String,
+    statics: Vec<(String, isize)>,
+    /// 1時間あたりと1周あたりの列
+    rates: Option<Rates>,
+}
+
+#[derive(TemplateOnce)]
+#[template(path = "dungeon_reward.stpl")]
+struct GenerateDungeonRewardStaticsTemplate {
+    name: String,
+    statics: Vec<(String, DungeonRewardElement)>,
+}
+#[derive(TemplateOnce)]
+#[template(path = "inner_floor.stpl")]
+struct InFloorStaticsTemplate {
+    name: String,
+    set_of_statics: Vec<GeneralStaticsTemplate>,
+}
+#[derive(TemplateOnce)]
+#[template(path = "sessions.stpl")]
+struct SessionsTemplate {
+    counters: Vec<String>,
+    sessions: Vec<SessionRow>,
+}
+struct SessionRow {
+    file: String,
+    start: String,
+    end: String,
+    duration: String,
+    totals: Vec<isize>,
+}
+#[derive(TemplateOnce)]
+#[template(path = "gacha_stats.stpl")]
+struct GachaStatsTemplate {
+    name: String,
+    /// サーバー全体の回数の列
+    rare: bool,
+    analytics: GachaAnalytics,
+}
+#[derive(TemplateOnce)]
+#[template(path = "lab_ledger.stpl")]
+struct LabLedgerTemplate {
+    name: String,
+    ledger: LabLedger,
+}
+#[derive(TemplateOnce)]
+#[template(path = "travel.stpl")]
+struct TravelTemplate {
+    name: String,
+    report: TravelReport,
+}
+#[derive(TemplateOnce)]
+#[template(path = "rare_feed.stpl")]
+struct RareFeedTemplate {
+    name: String,
+    feed: RareFeed,
+}
+#[derive(TemplateOnce)]
+#[template(path = "runs.stpl")]
+struct RunsTemplate {
+    dungeon: Option<String>,
+    records: Vec<LapRecordRow>,
+    clear_rates: Vec<ClearRateRow>,
+    runs: Vec<RunRow>,
+    income: RewardIncome,
+    income_per_hour: Option<RewardIncome>,
+}
+struct LapRecordRow {
+    dungeon: String,
+    runs: usize,
+    best: String,
+    average: String,
+}
+struct ClearRateRow {
+    dungeon: String,
+    clears: usize,
+    aborts: usize,
+    rate: String,
+}
+struct RunRow {
+    dungeon: String,
+    /// クリアか中断の理由
+    result: String,
+    start: String,
+    clear: String,
+    exit: String,
+    lap_time: String,
+    kills: isize,
+    items: isize,
+    parts: isize,
+    rewards: isize,
+    sells: isize,
+    income: RewardIncome,
+}
+#[derive(TemplateOnce)]
+#[template(path = "dungeon.stpl")]
+struct DungeonStaticsTemplate {
+    income: RewardIncome,
+    income_per_hour: Option<RewardIncome>,
+    lap_time: Option<chrono::Duration>,
+    /// このダンジョンの自己ベスト
+    best_lap: Option<chrono::Duration>,
+    /// 中断した理由
+    abort: Option<&'static str>,
+    dungeon_name: String,
+    splits: Vec<SplitRow>,
+    set_of_statics: Vec<GeneralStaticsTemplate>,
+}
+/// フロアごとのタイムと自己ベストとの差
+struct SplitRow {
+    floor: usize,
+    duration: String,
+    total: String,
+    best: String,
+    delta: String,
+    items: isize,
+    parts: isize,
+    kills: isize,
+}
+
+/// ルーティング
+/// 機能はそれぞれのハンドラで実装し,それ以外はworkフォルダの静的ファイルとして返す
+pub fn routes(cfg: &mut web::ServiceConfig) {
+    cfg.configure(api::routes)
+        .route("/dungeon_reward", web::get().to(dungeon_reward))
+        .route("/system", web::get().to(system))
+        .route("/dungeon", web::get().to(dungeon))
+        .route("/floor", web::get().to(floor))
+        .route("/sessions", web::get().to(sessions))
+        .route("/session", web::get().to(session))
+        .route("/runs", web::get().to(runs))
+        .route("/rare_feed", web::get().to(rare_feed))
+        .route("/gacha_stats", web::get().to(gacha_stats))
+        .route("/travel", web::get().to(travel))
+        .route("/lab_ledger", web::get().to(lab_ledger))
+        .route("/events", web::get().to(push::events))
+        .route(
+            "/{counter:items|parts|kills|labo|use|gacha|rare_gacha|dungeon_clear|burst|mission|shuttle}",
+            web::get().to(counter),
+        )
+        .service(
+            Files::new("/", ".")
+                .index_file("index.html")
+                .default_handler(fn_service(|request: ServiceRequest| async {
+                    let (request, _) = request.into_parts();
+                    Ok(ServiceResponse::new(request, not_found()))
+                })),
+        );
+}
+
+fn not_found() -> HttpResponse {
+    HttpResponse::NotFound()
+        .content_type(ContentType::html())
+        .body(include_str!("not_found.html"))
+}
+
+/// ブロッキングな処理をスレッドプールで実行してHTMLとして返す
+/// 設定ファイルが読めないときは設定ファイルの生成を試みる
+async fn render<F>(context: web::Data<Context>, page: F) -> HttpResponse
+where
+    F: FnOnce(&Context, &Setting) -> Vec<u8> + Send + 'static,
+{
+    let body = web::block(move || match context.config() {
+        Some(config) => page(&context, &config),
+        None => config_missing(&context),
+    })
+    .await;
+    match body {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(body),
+        Err(error) => {
+            eprintln!("{}", error);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+//設定ファイルが読めないとき
+fn config_missing(context: &Context) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(512);
+
+    match get_path_from_launcher() {
+        Ok(setting) => {
+            //write setting
+            #[cfg(debug_assertions)]
+            println!("Setting generated");
+            let config_file_content = toml::to_string(&setting).unwrap();
+            std::fs::write("./Settings.toml", config_file_content).unwrap();
+            rules::install(&setting.rules);
+            let old_position = context.config.write().unwrap().replace(setting);
+            #[cfg(debug_assertions)]
+            println!("{:#?}", old_position);
+            #[cfg(debug_assertions)]
+            println!("{:#?}", context.config);
+            let mut config_not_found_page = File::open("config_not_found.html").unwrap();
+            config_not_found_page.read_to_end(&mut buffer).unwrap();
+        }
+        Err(_) => {
+            let mut please_start_launcher_page = File::open("please_start_launcher.html").unwrap();
+            please_start_launcher_page.read_to_end(&mut buffer).unwrap();
+        }
+    }
+    buffer
+}
+
+/// 書き込み中のログファイルの追記分を読み込む
+pub(crate) fn update_live<'a>(context: &'a Context, files: &ChatFiles) -> MutexGuard<'a, LiveLog> {
+    let mut live = context.live.lock().unwrap();
+    match files.active {
+        Some(ref active) => live.follow(active),
+        None => live.unfollow(),
+    }
+    live.update();
+    for run in live.take_runs() {
+        if let Err(error) = context.store.save_run(&run) {
+            eprintln!("{}", error);
+        }
+    }
+    live
+}
+
+async fn dungeon_reward(
+    context: web::Data<Context>,
+    query: web::Query<RangeQuery>,
+) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        dungeon_reward_page(context, config, &range)
+    })
+    .await
+}
+
+/// クエリから統計の期間を読み取る
+pub(crate) fn time_range(query: &RangeQuery) -> Result<TimeRange, String> {
+    TimeRange::from_query(query, chrono::Local::now().naive_local())
+}
+
+/// 期間を絞り込んだ統計
+/// キャッシュは全期間のものなので保存済みのイベントから数え直す
+fn range_statics(
+    context: &Context,
+    config: &Setting,
+    statics_address: StaticsAddress,
+    range: &TimeRange,
+) -> InnerStatics {
+    let files = context.chat_files(config);
+    let paths: Vec<String> = files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+        .cloned()
+        .collect();
+    let mut statics = InnerStatics::new();
+    for data in ingest::pool(&paths, |path| {
+        context.store.statics(path, statics_address, range)
+    }) {
+        statics = connect_hashmap(statics, data);
+    }
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            statics = connect_hashmap(statics, live.statics_in(statics_address, range));
+        }
+    }
+    statics
+}
+
+/// ダンジョン報酬と売却の統計 (reward,sell)
+pub(crate) fn dungeon_reward_statics(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+) -> (InnerStatics, InnerStatics) {
+    if !range.is_all() {
+        return (
+            range_statics(context, config, StaticsAddress::DungeonReward, range),
+            range_statics(context, config, StaticsAddress::DungeonSell, range),
+        );
+    }
+    let files = context.chat_files(config);
+    let mut reward_statics = context.general_statics[StaticsAddress::DungeonReward.as_uint()]
+        .lock()
+        .unwrap();
+    let mut sell_statics = context.general_statics[StaticsAddress::DungeonSell.as_uint()]
+        .lock()
+        .unwrap();
+    let need_to_load = sell_statics.query_cache(&files.finished);
+    let loaded = ingest::pool(&need_to_load, |path| {
+        (
+            context
+                .store
+                .statics(path, StaticsAddress::DungeonReward, &TimeRange::default()),
+            context
+                .store
+                .statics(path, StaticsAddress::DungeonSell, &TimeRange::default()),
+        )
+    });
+    for (reward, sell) in loaded {
+        reward_statics.update_statics(reward);
+        sell_statics.update_statics(sell);
+    }
+    let live = update_live(context, &files);
+    let (reward, sell) = (reward_statics.get_statics(), sell_statics.get_statics());
+
+    (
+        connect_hashmap(live.statics(StaticsAddress::DungeonReward).clone(), reward),
+        connect_hashmap(live.statics(StaticsAddress::DungeonSell).clone(), sell),
+    )
+}
+
+fn dungeon_reward_page(context: &Context, config: &Setting, range: &TimeRange) -> Vec<u8> {
+    let (set_reward, set_sell) = dungeon_reward_statics(context, config, range);
+    let mut vec_reward = hashmap_to_vec(&set_reward);
+    let mut vec_sell = hashmap_to_vec(&set_sell);
+
+    sort(&mut vec_reward, SortTarget::NAME, true);
+    sort(&mut vec_sell, SortTarget::NAME, true);
+    //ITEMSとLDSを統合して出力
+    let ctx = InFloorStaticsTemplate {
+        name: with_label("ダンジョン報酬".to_string(), range),
+        set_of_statics: vec![
+            GeneralStaticsTemplate {
+                name: "報酬".to_string(),
+                rates: Some(range_rates(
+                    context,
+                    config,
+                    StaticsAddress::DungeonReward,
+                    range,
+                )),
+                statics: vec_reward,
+            },
+            GeneralStaticsTemplate {
+                name: "売却".to_string(),
+                rates: Some(range_rates(
+                    context,
+                    config,
+                    StaticsAddress::DungeonSell,
+                    range,
+                )),
+                statics: vec_sell,
+            },
+        ],
+    };
+    let text = ctx.render_once().unwrap();
+    text.into_bytes()
+}
+
+async fn system(context: web::Data<Context>, request: HttpRequest) -> HttpResponse {
+    // process query
+    let query = request
+        .query_string()
+        .split('&')
+        .next()
+        .and_then(|query| query.split('=').next())
+        .unwrap_or("")
+        .to_string();
+    render(context, move |context, config| {
+        system_page(context, config, &query)
+    })
+    .await
+}
+
+fn system_page(context: &Context, config: &Setting, query: &str) -> Vec<u8> {
+    match query {
+        "generate_config" => {
+            if let Ok(setting) = get_path_from_launcher() {
+                #[cfg(debug_assertions)]
+                println!("Setting generated");
+                let config_file_content = toml::to_string(&setting).unwrap();
+                std::fs::write("./Settings.toml", config_file_content).unwrap();
+                Vec::from(include_str!("generated_config.html"))
+            } else {
+                Vec::from(include_str!("blank.html"))
+            }
+        }
+        "inject_mesa" => {
+            #[cfg(debug_assertions)]
+            println!("Injecting mesa");
+            let mut programs_path = config.base_path.clone();
+            programs_path.push_str("programs");
+            mesa_inject::inject_mesa(programs_path);
+            Vec::from(include_str!("injecting_mesa.html"))
+        }
+        "update_c21" => {
+            let _process = update(config);
+            Vec::from(include_str!("blank.html"))
+        }
+        "launch_cosmic" => {
+            if let Some(ref sender) = *context.launcher.lock().unwrap() {
+                sender
+                    .send(ProcessRequest::LaunchMain)
+                    .expect("Failed to send launch message");
+            }
+            Vec::from(include_str!("blank.html"))
+        }
+        "kill_cosmic" => {
+            if let Some(ref sender) = *context.launcher.lock().unwrap() {
+                sender
+                    .send(ProcessRequest::KillMain)
+                    .expect("Failed to send kill message");
+            }
+            Vec::from(include_str!("blank.html"))
+        }
+        "launch_stage_editor" => {
+            if let Some(ref sender) = *context.launcher.lock().unwrap() {
+                sender
+                    .send(ProcessRequest::LaunchStageEditor)
+                    .expect("Failed to send kill message");
+            }
+            Vec::from(include_str!("blank.html"))
+        }
+        "kill_stage_editor" => {
+            if let Some(ref sender) = *context.launcher.lock().unwrap() {
+                sender
+                    .send(ProcessRequest::KillStageEditor)
+                    .expect("Failed to send kill message");
+            }
+            Vec::from(include_str!("blank.html"))
+        }
+        "exit" => {
+            std::process::exit(0);
+        }
+        _ => Vec::from(include_str!("blank.html")),
+    }
+}
+
+async fn counter(
+    context: web::Data<Context>,
+    request: HttpRequest,
+    query: web::Query<RangeQuery>,
+) -> HttpResponse {
+    let statics_address = StaticsAddress::from_url(request.path()).unwrap();
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        counter_page(context, config, statics_address, &range)
+    })
+    .await
+}
+
+/// 過去のログのキャッシュと最新のログを合わせた統計
+/// 期間が指定されていればその期間だけ
+pub(crate) fn counter_statics(
+    context: &Context,
+    config: &Setting,
+    statics_address: StaticsAddress,
+    range: &TimeRange,
+) -> InnerStatics {
+    if !range.is_all() {
+        return range_statics(context, config, statics_address, range);
+    }
+    let files = context.chat_files(config);
+    let mut statics = context.general_statics[statics_address.as_uint()]
+        .lock()
+        .unwrap();
+    //更新が必要なものをリストアップ
+    let need_to_load = statics.query_cache(&files.finished);
+    let loaded = ingest::pool(&need_to_load, |path| {
+        context
+            .store
+            .statics(path, statics_address, &TimeRange::default())
+    });
+    for data in loaded {
+        statics.update_statics(data);
+    }
+    let items = statics.get_statics();
+    drop(statics);
+    let updating = update_live(context, &files)
+        .statics(statics_address)
+        .clone();
+
+    //ITEMSとLDSを統合して出力
+    connect_hashmap(items, updating)
+}
+
+fn counter_page(
+    context: &Context,
+    config: &Setting,
+    statics_address: StaticsAddress,
+    range: &TimeRange,
+) -> Vec<u8> {
+    let set = counter_statics(context, config, statics_address, range);
+    let rates = range_rates(context, config, statics_address, range);
+    let mut vector = hashmap_to_vec(&set);
+    sort(&mut vector, SortTarget::NAME, true);
+    let ctx = GeneralStaticsTemplate {
+        name: with_label(statics_address.to_string(), range),
+        statics: vector,
+        rates: Some(rates),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+/// セッションのログを最初から状態機械に流し直す
+pub(crate) fn replay_session(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+) -> Option<Replay> {
+    let files = context.chat_files(config);
+    let path = files
+        .finished
+        .iter()
+        .chain(files.active.iter())
+        .find(|path| range.includes_file(path))?;
+    Some(replay::replay(&read_events(path).ok()?))
+}
+
+/// 期間内の統計を1時間あたりと1周あたりにするための値
+/// 1周あたりは周回の中で数えた個数だけを使う
+pub(crate) fn range_rates(
+    context: &Context,
+    config: &Setting,
+    statics_address: StaticsAddress,
+    range: &TimeRange,
+) -> Rates {
+    let log_error = |error: rusqlite::Error| eprintln!("{}", error);
+    let mut rates = Rates::in_range(range, &session_summaries(context, config), |start, end| {
+        context
+            .store
+            .count_runs(start, end, range)
+            .map_err(log_error)
+            .unwrap_or_default()
+    });
+    let files = context.chat_files(config);
+    for path in files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+    {
+        let in_runs = context
+            .store
+            .ingest(path)
+            .and_then(|id| context.store.count_in_runs(id, statics_address, range));
+        match in_runs {
+            Ok(in_runs) => rates.in_runs = connect_hashmap(rates.in_runs, in_runs),
+            Err(error) => log_error(error),
+        }
+    }
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            let events = live.events();
+            if let (Some(first), Some(last)) = (events.first(), events.last()) {
+                let windows = context
+                    .store
+                    .run_windows(first.time, last.time)
+                    .map_err(log_error)
+                    .unwrap_or_default();
+                for event in events {
+                    let in_run = windows
+                        .iter()
+                        .any(|(start, exit)| *start <= event.time && event.time <= *exit);
+                    if !in_run || !range.contains(event.time) {
+                        continue;
+                    }
+                    if let Some((name, qty)) = event.event.count(statics_address) {
+                        add_to_table(&mut rates.in_runs, name, qty);
+                    }
+                }
+            }
+        }
+    }
+    rates
+}
+
+/// 見出しに期間を付ける
+fn with_label(name: String, range: &TimeRange) -> String {
+    match range.label() {
+        Some(label) => format!("{} ({})", name, label),
+        None => name,
+    }
+}
+
+async fn dungeon(context: web::Data<Context>) -> HttpResponse {
+    render(context, dungeon_page).await
+}
+
+/// 状態機械に最新のログを供給して現在の状態とダンジョン内の統計を返す
+/// ダンジョンの区切りが確定したらHTMLとして保存する
+pub(crate) fn dungeon_statics(
+    context: &Context,
+    config: &Setting,
+) -> (DungeonState, Option<DungeonOutPut>) {
+    let files = context.chat_files(config);
+    //ファイルが切り替わると状態機械もリセットされる
+    let mut live = update_live(context, &files);
+    let state_machine = live.state_machine();
+    let state = *state_machine.inspect_state();
+
+    println!("current state {:?}", state);
+    let statics = state_machine.statics();
+    if let Some(ref statics) = statics {
+        //out put log
+        if let (Some(range), Some(last)) = (state_machine.query_dungeon_range(), files.active) {
+            let bytes = dungeon_template(statics, best_run(context, statics))
+                .render_once()
+                .unwrap()
+                .into_bytes();
+            let path = Path::new(&last);
+            let stem = path.file_stem().unwrap();
+            let stem = stem.to_str().unwrap();
+
+            if !Path::new("./dungeon_statics").exists() {
+                std::fs::create_dir("./dungeon_statics").unwrap();
+            }
+            let file_name = format!(
+                "./dungeon_statics/{}@{}_{}.html",
+                stem, range.start, range.end
+            );
+            let mut file = std::fs::File::create(file_name).unwrap();
+
+            file.write_all(&bytes).unwrap();
+            file.flush().unwrap();
+        }
+    }
+    (state, statics)
+}
+
+/// 保存済みの周回から今のダンジョンの最速の周回を探す
+pub(crate) fn best_run(context: &Context, statics: &DungeonOutPut) -> Option<DungeonRun> {
+    let dungeon = statics.dungeon.as_ref()?;
+    match context.store.best_run(dungeon) {
+        Ok(best) => best,
+        Err(error) => {
+            eprintln!("{}", error);
+            None
+        }
+    }
+}
+
+/// 各フロアまでの累計タイムを自己ベストと比べる
+fn split_rows(splits: &[FloorSplit], best: Option<&DungeonRun>) -> Vec<SplitRow> {
+    let best = best.map_or(&[][..], |best| &best.statics.splits[..]);
+    let mut total = 0;
+    let mut best_total = 0;
+    splits
+        .iter()
+        .enumerate()
+        .map(|(index, split)| {
+            total += split.duration;
+            let (best, delta) = match best.get(index) {
+                Some(best) => {
+                    best_total += best.duration;
+                    let delta = total - best_total;
+                    let sign = if delta < 0 { "-" } else { "+" };
+                    (
+                        format_seconds(best_total),
+                        format!("{}{}", sign, format_seconds(delta.abs())),
+                    )
+                }
+                None => Default::default(),
+            };
+            SplitRow {
+                floor: index + 1,
+                duration: format_seconds(split.duration),
+                total: format_seconds(total),
+                best,
+                delta,
+                items: split.items,
+                parts: split.parts,
+                kills: split.kills,
+            }
+        })
+        .collect()
+}
+
+fn dungeon_template(statics: &DungeonOutPut, best: Option<DungeonRun>) -> DungeonStaticsTemplate {
+    let income = statics.income();
+    let rates = Rates::in_run(statics.lap_time);
+    DungeonStaticsTemplate {
+        income,
+        income_per_hour: statics
+            .lap_time
+            .and_then(|lap_time| income.per_hour(lap_time.num_seconds())),
+        lap_time: statics.lap_time,
+        best_lap: best.as_ref().and_then(|best| best.lap_time),
+        abort: statics.abort.map(|abort| abort.label()),
+        splits: split_rows(&statics.splits, best.as_ref()),
+        dungeon_name: statics
+            .dungeon
+            .clone()
+            .unwrap_or_else(|| "ダンジョン内カウント".to_string()),
+        set_of_statics: vec![
+            GeneralStaticsTemplate {
+                name: "アイテム取得".to_string(),
+                rates: Some(rates.clone()),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.statics[0]);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+            GeneralStaticsTemplate {
+                name: "パーツ取得".to_string(),
+                rates: Some(rates.clone()),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.statics[2]);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+            GeneralStaticsTemplate {
+                name: "アイテム使用".to_string(),
+                rates: Some(rates.clone()),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.statics[1]);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+            GeneralStaticsTemplate {
+                name: "キル".to_string(),
+                rates: Some(rates.clone()),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.statics[3]);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+            GeneralStaticsTemplate {
+                name: "報酬".to_string(),
+                rates: Some(rates.clone()),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.rewards);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+            GeneralStaticsTemplate {
+                name: "報酬売却".to_string(),
+                rates: Some(rates),
+                statics: {
+                    let mut vector = hashmap_to_vec(&statics.sells);
+                    sort(&mut vector, SortTarget::NAME, true);
+                    vector
+                },
+            },
+        ],
+    }
+}
+
+fn dungeon_page(context: &Context, config: &Setting) -> Vec<u8> {
+    match dungeon_statics(context, config).1 {
+        Some(statics) => dungeon_template(&statics, best_run(context, &statics))
+            .render_once()
+            .unwrap()
+            .into_bytes(),
+        None => Vec::from(include_str!("not_entered.html")),
+    }
+}
+
+async fn floor(context: web::Data<Context>) -> HttpResponse {
+    render(context, floor_page).await
+}
+
+/// 最後のフロアゲート以降の統計
+/// [アイテム取得,パーツ取得,アイテム使用,キル]
+pub(crate) fn floor_statics(context: &Context, config: &Setting) -> Option<Vec<InnerStatics>> {
+    let files = context.chat_files(config);
+    let live = update_live(context, &files);
+    let events = live.events();
+    let from = search_floor_last(events, 0)?;
+    Some(vec![
+        engine_item_get(events, from),
+        engine_get_part(events, from),
+        engine_item_use(events, from),
+        engine_kill_self(events, from),
+    ])
+}
+
+fn floor_page(context: &Context, config: &Setting) -> Vec<u8> {
+    match floor_statics(context, config) {
+        None => Vec::from(include_str!("not_entered.html")),
+        Some(lds) => {
+            let ctx = InFloorStaticsTemplate {
+                name: "フロア内カウント".to_string(),
+                set_of_statics: vec![
+                    GeneralStaticsTemplate {
+                        name: "アイテム取得".to_string(),
+                        rates: None,
+                        statics: {
+                            let mut vector = hashmap_to_vec(&lds[0]);
+                            sort(&mut vector, SortTarget::NAME, true);
+                            vector
+                        },
+                    },
+                    GeneralStaticsTemplate {
+                        name: "パーツ取得".to_string(),
+                        rates: None,
+                        statics: {
+                            let mut vector = hashmap_to_vec(&lds[1]);
+                            sort(&mut vector, SortTarget::NAME, true);
+                            vector
+                        },
+                    },
+                    GeneralStaticsTemplate {
+                        name: "アイテム使用".to_string(),
+                        rates: None,
+                        statics: {
+                            let mut vector = hashmap_to_vec(&lds[2]);
+                            sort(&mut vector, SortTarget::NAME, true);
+                            vector
+                        },
+                    },
+                    GeneralStaticsTemplate {
+                        name: "キル".to_string(),
+                        rates: None,
+                        statics: {
+                            let mut vector = hashmap_to_vec(&lds[3]);
+                            sort(&mut vector, SortTarget::NAME, true);
+                            vector
+                        },
+                    },
+                ],
+            };
+            let table = ctx.render_once().unwrap();
+            table.into_bytes()
+        }
+    }
+}
+
+async fn sessions(context: web::Data<Context>) -> HttpResponse {
+    render(context, sessions_page).await
+}
+
+/// chatフォルダのログファイルごとのまとめ(新しい順)
+pub(crate) fn session_summaries(context: &Context, config: &Setting) -> Vec<SessionSummary> {
+    let files = context.chat_files(config);
+    //まとめは読み込んだときにデータベースに保存してある
+    let mut summaries: Vec<SessionSummary> = files
+        .finished
+        .iter()
+        .map(|path| {
+            context
+                .store
+                .ingest(path)
+                .and_then(|id| context.store.summary(id, path))
+        })
+        .filter_map(|summary| match summary {
+            Ok(summary) => Some(summary),
+            Err(error) => {
+                eprintln!("{}", error);
+                None
+            }
+        })
+        .collect();
+    if let Some(ref active) = files.active {
+        let live = update_live(context, &files);
+        summaries.push(SessionSummary::from_events(active, true, live.events()));
+    }
+    summaries.reverse();
+    summaries
+}
+
+fn sessions_page(context: &Context, config: &Setting) -> Vec<u8> {
+    let sessions = session_summaries(context, config)
+        .into_iter()
+        .map(|summary| SessionRow {
+            start: format_time(summary.start),
+            end: format_time(summary.end),
+            duration: summary
+                .duration()
+                .map(|duration| format_seconds(duration.num_seconds()))
+                .unwrap_or_default(),
+            file: summary.file,
+            totals: summary.totals,
+        })
+        .collect();
+    let ctx = SessionsTemplate {
+        counters: SESSION_COUNTERS
+            .iter()
+            .map(|statics_address| statics_address.to_string())
+            .collect(),
+        sessions,
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+async fn session(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) if range.session.is_some() => range,
+        Ok(_) => return HttpResponse::BadRequest().body("session is required"),
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        session_page(context, config, &range)
+    })
+    .await
+}
+
+/// 1つのログファイルのカウンタごとの統計
+pub(crate) fn session_statics(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+) -> Vec<(StaticsAddress, InnerStatics)> {
+    SESSION_COUNTERS
+        .iter()
+        .map(|statics_address| {
+            (
+                *statics_address,
+                counter_statics(context, config, *statics_address, range),
+            )
+        })
+        .collect()
+}
+
+fn session_page(context: &Context, config: &Setting, range: &TimeRange) -> Vec<u8> {
+    let set_of_statics = session_statics(context, config, range)
+        .into_iter()
+        .map(|(statics_address, statics)| {
+            let mut vector = hashmap_to_vec(&statics);
+            sort(&mut vector, SortTarget::NAME, true);
+            GeneralStaticsTemplate {
+                name: statics_address.to_string(),
+                rates: Some(range_rates(context, config, statics_address, range)),
+                statics: vector,
+            }
+        })
+        .collect();
+    let ctx = InFloorStaticsTemplate {
+        name: range.label().unwrap_or_default(),
+        set_of_statics,
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+/// 周回履歴の絞り込みと並べ替え
+/// ?dungeon=ダンジョン名&sort=start|lap_time|dungeon&desc
+/// descはどの並べ方でも降順 sortがなければ開始の新しい順
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct RunQuery {
+    pub(crate) dungeon: Option<String>,
+    pub(crate) sort: Option<String>,
+    pub(crate) desc: Option<String>,
+}
+
+async fn rare_feed(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        rare_feed_page(context, config, &range)
+    })
+    .await
+}
+
+/// サーバー全体のレアガチャの当たりを時刻順に集めて,自分のガチャの統計と並べる
+pub(crate) fn rare_feed_statics(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+) -> RareFeed {
+    let files = context.chat_files(config);
+    let paths: Vec<String> = files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+        .cloned()
+        .collect();
+    let mut wins: Vec<(chrono::NaiveDateTime, String)> = ingest::pool(&paths, |path| {
+        context
+            .store
+            .events_of(path, StaticsAddress::RareGacha, range)
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            wins.extend(
+                live.events()
+                    .iter()
+                    .filter(|event| range.contains(event.time))
+                    .filter_map(|event| {
+                        let (name, _) = event.event.count(StaticsAddress::RareGacha)?;
+                        Some((event.time, name.to_string()))
+                    }),
+            );
+        }
+    }
+    wins.sort_by_key(|(time, _)| *time);
+    let own = counter_statics(context, config, StaticsAddress::Gacha, range);
+    RareFeed::new(&wins, &own)
+}
+
+fn rare_feed_page(context: &Context, config: &Setting, range: &TimeRange) -> Vec<u8> {
+    let ctx = RareFeedTemplate {
+        name: with_label("レアガチャ(サーバー全体)".to_string(), range),
+        feed: rare_feed_statics(context, config, range),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+/// ?rare でサーバー全体のレアガチャの回数を並べる
+#[derive(Deserialize)]
+pub(crate) struct GachaQuery {
+    #[serde(flatten)]
+    pub(crate) range: RangeQuery,
+    pub(crate) rare: Option<String>,
+}
+
+async fn gacha_stats(context: web::Data<Context>, query: web::Query<GachaQuery>) -> HttpResponse {
+    let range = match time_range(&query.range) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    let rare = query.rare.is_some();
+    render(context, move |context, config| {
+        gacha_stats_page(context, config, &range, rare)
+    })
+    .await
+}
+
+/// ログからガチャのまわしを組み立てて出現率を推定する
+pub(crate) fn gacha_statics(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+    rare: bool,
+) -> GachaAnalytics {
+    let files = context.chat_files(config);
+    let paths: Vec<String> = files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+        .cloned()
+        .collect();
+    let mut pulls: Vec<_> = ingest::pool(&paths, |path| context.store.pulls(path, range))
+        .into_iter()
+        .flatten()
+        .collect();
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            let events: Vec<_> = live
+                .events()
+                .iter()
+                .filter(|event| range.contains(event.time))
+                .cloned()
+                .collect();
+            pulls.extend(engine_gacha_pulls(&events, 0));
+        }
+    }
+    pulls.sort_by_key(|pull| pull.time);
+    let rare = if rare {
+        Some(counter_statics(
+            context,
+            config,
+            StaticsAddress::RareGacha,
+            range,
+        ))
+    } else {
+        None
+    };
+    GachaAnalytics::new(&pulls, rare.as_ref())
+}
+
+fn gacha_stats_page(context: &Context, config: &Setting, range: &TimeRange, rare: bool) -> Vec<u8> {
+    let ctx = GachaStatsTemplate {
+        name: with_label("ガチャ分析".to_string(), range),
+        rare,
+        analytics: gacha_statics(context, config, range, rare),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+async fn travel(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        travel_page(context, config, &range)
+    })
+    .await
+}
+
+/// ジャンプ,ゲート,シャトルの移動の履歴と場所ごとの滞在時間
+pub(crate) fn travel_statics(
+    context: &Context,
+    config: &Setting,
+    range: &TimeRange,
+) -> TravelReport {
+    let files = context.chat_files(config);
+    let paths: Vec<String> = files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+        .cloned()
+        .collect();
+    let mut sessions: Vec<_> = ingest::pool(&paths, |path| context.store.travels(path, range))
+        .into_iter()
+        .flatten()
+        .collect();
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            let events: Vec<_> = live
+                .events()
+                .iter()
+                .filter(|event| range.contains(event.time))
+                .cloned()
+                .collect();
+            if let Some(last) = events.last() {
+                sessions.push((engine_travel(&events, 0), last.time));
+            }
+        }
+    }
+    sessions.sort_by_key(|(_, end)| *end);
+    TravelReport::new(&sessions)
+}
+
+fn travel_page(context: &Context, config: &Setting, range: &TimeRange) -> Vec<u8> {
+    let ctx = TravelTemplate {
+        name: with_label("移動履歴".to_string(), range),
+        report: travel_statics(context, config, range),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+async fn lab_ledger(context: web::Data<Context>, query: web::Query<RangeQuery>) -> HttpResponse {
+    let range = match time_range(&query) {
+        Ok(range) => range,
+        Err(error) => return HttpResponse::BadRequest().body(error),
+    };
+    render(context, move |context, config| {
+        lab_ledger_page(context, config, &range)
+    })
+    .await
+}
+
+/// 合成の成功と失敗,レシピごとの成功率と材料の消費
+pub(crate) fn lab_statics(context: &Context, config: &Setting, range: &TimeRange) -> LabLedger {
+    let files = context.chat_files(config);
+    let paths: Vec<String> = files
+        .finished
+        .iter()
+        .filter(|path| range.includes_file(path))
+        .cloned()
+        .collect();
+    //材料のない失敗を前のファイルの合成につなげないようにファイルごとに渡す
+    let mut sessions = ingest::pool(&paths, |path| context.store.syntheses(path, range));
+    if let Some(ref active) = files.active {
+        if range.includes_file(active) {
+            let live = update_live(context, &files);
+            let events: Vec<_> = live
+                .events()
+                .iter()
+                .filter(|event| range.contains(event.time))
+                .cloned()
+                .collect();
+            sessions.push(engine_synthesis(&events, 0));
+        }
+    }
+    LabLedger::new(&sessions)
+}
+
+fn lab_ledger_page(context: &Context, config: &Setting, range: &TimeRange) -> Vec<u8> {
+    let ctx = LabLedgerTemplate {
+        name: with_label("合成記録".to_string(), range),
+        ledger: lab_statics(context, config, range),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
+
+async fn runs(context: web::Data<Context>, query: web::Query<RunQuery>) -> HttpResponse {
+    let query = query.into_inner();
+    render(context, move |context, _config| runs_page(context, &query)).await
+}
+
+/// ダンジョンごとのラップタイムとクリア率と周回の一覧
+pub(crate) fn run_history(
+    context: &Context,
+    query: &RunQuery,
+) -> rusqlite::Result<(Vec<LapRecord>, Vec<ClearRate>, Vec<DungeonRun>)> {
+    let records = context.store.lap_records()?;
+    let clear_rates = context.store.clear_rates()?;
+    let mut runs = context.store.runs(query.dungeon.as_deref())?;
+    match query.sort.as_deref() {
+        Some("lap_time") => runs.sort_by_key(|run| run.lap_time),
+        Some("dungeon") => runs.sort_by(|a, b| a.dungeon.cmp(&b.dungeon)),
+        _ => runs.sort_by_key(|run| run.start),
+    }
+    //並べ方を指定しなければ新しい順
+    if query.desc.is_some() || query.sort.is_none() {
+        runs.reverse();
+    }
+    //ラップタイムのない周回はどちらの向きでも後ろに置く
+    if query.sort.as_deref() == Some("lap_time") {
+        runs.sort_by_key(|run| run.lap_time.is_none());
+    }
+    Ok((records, clear_rates, runs))
+}
+
+/// 周回の収入の合計と,ダンジョンにいた時間あたりの収入
+pub(crate) fn total_income(runs: &[DungeonRun]) -> (RewardIncome, Option<RewardIncome>) {
+    let mut income = RewardIncome::default();
+    let mut seconds = 0;
+    for run in runs {
+        income.add(&run.statics.income);
+        seconds += (run.exit - run.start).num_seconds();
+    }
+    (income, income.per_hour(seconds))
+}
+
+fn runs_page(context: &Context, query: &RunQuery) -> Vec<u8> {
+    let (records, clear_rates, runs) = match run_history(context, query) {
+        Ok(history) => history,
+        Err(error) => {
+            eprintln!("{}", error);
+            Default::default()
+        }
+    };
+    let name = |dungeon: Option<String>| dungeon.unwrap_or_else(|| "不明".to_string());
+    let total = |statics: &InnerStatics| statics.values().sum();
+    let (income, income_per_hour) = total_income(&runs);
+    let ctx = RunsTemplate {
+        income,
+        income_per_hour,
+        dungeon: query.dungeon.clone(),
+        records: records
+            .into_iter()
+            .map(|record| LapRecordRow {
+                dungeon: name(record.dungeon),
+                runs: record.runs,
+                best: format_seconds(record.best),
+                average: format_seconds(record.average.round() as i64),
+            })
+            .collect(),
+        clear_rates: clear_rates
+            .into_iter()
+            .map(|clear_rate| ClearRateRow {
+                dungeon: name(clear_rate.dungeon),
+                clears: clear_rate.clears,
+                aborts: clear_rate.aborts,
+                rate: format_share(Some(clear_rate.rate)),
+            })
+            .collect(),
+        runs: runs
+            .into_iter()
+            .map(|run| RunRow {
+                result: match run.abort {
+                    Some(abort) => format!("中断({})", abort.label()),
+                    None => "クリア".to_string(),
+                },
+                start: format_time(Some(run.start)),
+                clear: format_time(run.clear),
+                exit: format_time(Some(run.exit)),
+                lap_time: run
+                    .lap_time
+                    .map(|lap_time| format_seconds(lap_time.num_seconds()))
+                    .unwrap_or_default(),
+                kills: total(&run.statics.kills),
+                items: total(&run.statics.items),
+                parts: total(&run.statics.parts),
+                rewards: total(&run.statics.rewards),
+                sells: total(&run.statics.sells),
+                income: run.statics.income,
+                dungeon: name(run.dungeon),
+            })
+            .collect(),
+    };
+    ctx.render_once().unwrap().into_bytes()
+}
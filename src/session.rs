@@ -0,0 +1,68 @@
+use std::path::Path;
+
+use chrono::NaiveDateTime;
+
+use crate::log_event::TimedEvent;
+use crate::statics_address::StaticsAddress;
+
+/// セッション一覧に合計を表示するカウンタ
+pub const SESSION_COUNTERS: [StaticsAddress; 12] = [
+    StaticsAddress::Item,
+    StaticsAddress::Parts,
+    StaticsAddress::ItemUse,
+    StaticsAddress::Kill,
+    StaticsAddress::Lab,
+    StaticsAddress::Gacha,
+    StaticsAddress::DungeonClear,
+    StaticsAddress::DungeonReward,
+    StaticsAddress::DungeonSell,
+    StaticsAddress::Burst,
+    StaticsAddress::Mission,
+    StaticsAddress::Shuttle,
+];
+
+/// ログファイル1つ分(ゲームの起動から終了まで)のまとめ
+#[derive(Clone, Debug)]
+pub struct SessionSummary {
+    /// ログファイル名
+    pub file: String,
+    /// 書き込み中のログ
+    pub active: bool,
+    pub start: Option<NaiveDateTime>,
+    pub end: Option<NaiveDateTime>,
+    /// SESSION_COUNTERSと同じ順の合計
+    pub totals: Vec<isize>,
+}
+
+impl SessionSummary {
+    pub fn new(path: &str, active: bool) -> Self {
+        let file = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path)
+            .to_string();
+        Self {
+            file,
+            active,
+            start: None,
+            end: None,
+            totals: vec![0; SESSION_COUNTERS.len()],
+        }
+    }
+    pub fn from_events(path: &str, active: bool, events: &[TimedEvent]) -> Self {
+        let mut summary = Self::new(path, active);
+        summary.start = events.first().map(|event| event.time);
+        summary.end = events.last().map(|event| event.time);
+        for event in events {
+            for (total, statics_address) in summary.totals.iter_mut().zip(&SESSION_COUNTERS) {
+                if let Some((_, qty)) = event.event.count(*statics_address) {
+                    *total += qty;
+                }
+            }
+        }
+        summary
+    }
+    pub fn duration(&self) -> Option<chrono::Duration> {
+        Some(self.end? - self.start?)
+    }
+}
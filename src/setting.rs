@@ -8,6 +8,22 @@ pub struct Setting {
     pub launcher_name: String,
     pub base_path: String,
     pub port: u16,
+    /// ダンジョンの状態機械のルール
+    #[serde(default = "default_rules")]
+    pub rules: String,
+}
+
+fn default_rules() -> String {
+    "./dungeon_rules.toml".to_string()
+}
+
+impl Setting {
+    /// チャットログのフォルダ
+    pub fn chat_dir(&self) -> String {
+        let mut chat_path = self.base_path.clone();
+        chat_path.push_str("chat/");
+        chat_path
+    }
 }
 
 pub enum GetPathError {
@@ -66,6 +82,7 @@ pub fn get_path_from_launcher() -> Result<Setting, GetPathError> {
         launcher_name: launcher_name.to_string(),
         base_path,
         port: 7878,
+        rules: default_rules(),
     };
     println!("{:#?}", setting);
     Ok(setting)
@@ -1,3 +1,32 @@
+use std::collections::HashMap;
+
+use crate::utils::load_tsv;
+
+/// tsvの辞書の置き場所
+/// テストはクレートのルートで実行されるのでworkフォルダを読む
+#[cfg(not(test))]
+const DICTIONARY_DIR: &str = "./";
+#[cfg(test)]
+const DICTIONARY_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/work/");
+
+lazy_static! {
+    static ref DICTIONARIES: Vec<HashMap<String, String>> = {
+        //辞書がなければそのカウンタは何も数えない
+        let load = |name: &str| {
+            let path = format!("{}{}", DICTIONARY_DIR, name);
+            load_tsv(&path).unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                HashMap::new()
+            })
+        };
+        let shuttle_tsv = load("shuttle.tsv");
+        let dungeon_tsv = load("dungeon.tsv");
+        let mission_tsv = load("mission.tsv");
+        let burst_tsv = load("burst.tsv");
+        vec![burst_tsv, dungeon_tsv, mission_tsv, shuttle_tsv]
+    };
+}
+
 #[derive(Clone, Copy)]
 pub enum StaticsAddress {
     Item,
@@ -9,23 +38,56 @@ pub enum StaticsAddress {
     Shuttle,
     Lab,
     Gacha,
+    /// 誰かがガチャセンターで当てたレアアイテム
+    RareGacha,
     DungeonReward,
     DungeonSell,
     DungeonClear,
 }
 impl StaticsAddress {
+    /// 個別のページを持つカウンタ
+    pub const COUNTERS: [StaticsAddress; 11] = [
+        StaticsAddress::Item,
+        StaticsAddress::ItemUse,
+        StaticsAddress::Parts,
+        StaticsAddress::Kill,
+        StaticsAddress::Burst,
+        StaticsAddress::Mission,
+        StaticsAddress::Shuttle,
+        StaticsAddress::Lab,
+        StaticsAddress::Gacha,
+        StaticsAddress::RareGacha,
+        StaticsAddress::DungeonClear,
+    ];
     pub fn from_url(url: &str) -> Option<Self> {
         match url {
-            "./items" => Some(Self::Item),
-            "./parts" => Some(Self::Parts),
-            "./use" => Some(Self::ItemUse),
-            "./kills" => Some(Self::Kill),
-            "./labo" => Some(Self::Lab),
-            "./gacha" => Some(Self::Gacha),
-            "./dungeon_clear" => Some(Self::DungeonClear),
-            "./burst" => Some(Self::Burst),
-            "./mission" => Some(Self::Mission),
-            "./shuttle" => Some(Self::Shuttle),
+            "/items" => Some(Self::Item),
+            "/parts" => Some(Self::Parts),
+            "/use" => Some(Self::ItemUse),
+            "/kills" => Some(Self::Kill),
+            "/labo" => Some(Self::Lab),
+            "/gacha" => Some(Self::Gacha),
+            "/rare_gacha" => Some(Self::RareGacha),
+            "/dungeon_clear" => Some(Self::DungeonClear),
+            "/burst" => Some(Self::Burst),
+            "/mission" => Some(Self::Mission),
+            "/shuttle" => Some(Self::Shuttle),
+            _ => None,
+        }
+    }
+    pub fn to_url(self) -> Option<&'static str> {
+        match self {
+            StaticsAddress::Item => Some("/items"),
+            StaticsAddress::Parts => Some("/parts"),
+            StaticsAddress::ItemUse => Some("/use"),
+            StaticsAddress::Kill => Some("/kills"),
+            StaticsAddress::Lab => Some("/labo"),
+            StaticsAddress::Gacha => Some("/gacha"),
+            StaticsAddress::RareGacha => Some("/rare_gacha"),
+            StaticsAddress::DungeonClear => Some("/dungeon_clear"),
+            StaticsAddress::Burst => Some("/burst"),
+            StaticsAddress::Mission => Some("/mission"),
+            StaticsAddress::Shuttle => Some("/shuttle"),
             _ => None,
         }
     }
@@ -43,6 +105,7 @@ impl StaticsAddress {
             StaticsAddress::Gacha => 9,
             StaticsAddress::DungeonReward => 10,
             StaticsAddress::DungeonSell => 11,
+            StaticsAddress::RareGacha => 12,
         }
     }
     pub fn as_dictionary_index(&self) -> Option<usize> {
@@ -55,6 +118,10 @@ impl StaticsAddress {
             _ => None,
         }
     }
+    /// tsvで照合するカウンタの辞書
+    pub fn dictionary(&self) -> Option<&'static HashMap<String, String>> {
+        self.as_dictionary_index().map(|index| &DICTIONARIES[index])
+    }
 }
 impl ToString for StaticsAddress {
     fn to_string(&self) -> String {
@@ -69,6 +136,7 @@ impl ToString for StaticsAddress {
             StaticsAddress::Shuttle => "シャトル",
             StaticsAddress::Lab => "合成",
             StaticsAddress::Gacha => "ガチャ",
+            StaticsAddress::RareGacha => "レアガチャ(サーバー全体)",
             StaticsAddress::DungeonReward => "ダンジョン報酬",
             StaticsAddress::DungeonSell => "ダンジョン報酬売却",
         };
@@ -0,0 +1,920 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::dungeon_state_machine::{DungeonRun, RunStatics};
+use crate::engines::{add_to_table, engine_count, InnerStatics};
+use crate::gacha::{engine_gacha_pulls, Pull};
+use crate::lab::{engine_synthesis, Synthesis};
+use crate::log_event::{LogEvent, TimedEvent};
+use crate::replay::replay;
+use crate::rules;
+use crate::session::SessionSummary;
+use crate::statics_address::StaticsAddress;
+use crate::time_range::TimeRange;
+use crate::travel::{engine_travel, Travel};
+use crate::utils::read_events;
+
+/// 統計データベースのファイル
+pub const STORE_PATH: &str = "statics.db";
+
+/// ログの分類を変えたら上げる
+/// 保存済みのイベントは全て読み直す
+const PARSER_VERSION: u32 = 2;
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    mtime INTEGER NOT NULL,
+    first_time TEXT,
+    last_time TEXT,
+    totals TEXT
+);
+CREATE TABLE IF NOT EXISTS events (
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    line INTEGER NOT NULL,
+    time TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    qty INTEGER NOT NULL,
+    materials TEXT,
+    PRIMARY KEY (file_id, line)
+);
+CREATE INDEX IF NOT EXISTS events_kind ON events (kind, time);
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    dungeon TEXT,
+    start TEXT NOT NULL UNIQUE,
+    clear TEXT,
+    exit TEXT NOT NULL,
+    lap_time INTEGER,
+    statics TEXT NOT NULL,
+    abort TEXT,
+    file_id INTEGER REFERENCES files(id) ON DELETE CASCADE
+);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// ログファイルごとに分類済みのイベントを保存しておく
+/// サイズと更新日時が変わっていなければ読み直さない
+pub struct EventStore {
+    connection: Mutex<Connection>,
+}
+
+impl EventStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+    fn init(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        //abortの列がない古いデータベース
+        if connection.prepare("SELECT abort FROM runs").is_err() {
+            connection.execute_batch("ALTER TABLE runs ADD COLUMN abort TEXT")?;
+        }
+        //周回がどのファイルから読んだものかわからない古いデータベースは全て読み直す
+        if connection.prepare("SELECT file_id FROM runs").is_err() {
+            connection.execute_batch(
+                "ALTER TABLE runs ADD COLUMN file_id INTEGER REFERENCES files(id) ON DELETE CASCADE;
+                 DELETE FROM files;",
+            )?;
+        }
+        //まとめを持たない古いデータベースは全て読み直す
+        if connection.prepare("SELECT totals FROM files").is_err() {
+            connection.execute_batch(
+                "ALTER TABLE files ADD COLUMN first_time TEXT;
+                 ALTER TABLE files ADD COLUMN last_time TEXT;
+                 ALTER TABLE files ADD COLUMN totals TEXT;
+                 DELETE FROM files;",
+            )?;
+        }
+        //合成の材料と失敗を分類していない古いデータベースは全て読み直す
+        if connection.prepare("SELECT materials FROM events").is_err() {
+            connection.execute_batch(
+                "ALTER TABLE events ADD COLUMN materials TEXT;
+                 DELETE FROM files;",
+            )?;
+        }
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+    /// ファイルが前回と変わっていれば読み直して保存する
+    /// ログを状態機械に流し直して,ライブで記録できなかった周回も保存する
+    /// ファイルのidを返す
+    pub fn ingest(&self, path: &str) -> rusqlite::Result<i64> {
+        let (size, mtime) = file_stamp(path);
+        let connection = self.connection.lock().unwrap();
+        discard_stale(&connection)?;
+        let cached = connection
+            .query_row(
+                "SELECT id FROM files WHERE path = ?1 AND size = ?2 AND mtime = ?3",
+                params![path, size, mtime],
+                |row| row.get(0),
+            )
+            .optional()?;
+        drop(connection);
+        if let Some(id) = cached {
+            return Ok(id);
+        }
+        //読み込みはロックの外で行う
+        //読めなければ(走査の後に消えたなど)キャッシュせずにエラーとする
+        let events = read_events(path).map_err(|_| rusqlite::Error::InvalidPath(path.into()))?;
+        let runs = replay(&events).runs;
+        let summary = SessionSummary::from_events(path, false, &events);
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+        transaction.execute(
+            "INSERT INTO files (path, size, mtime, first_time, last_time, totals)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                path,
+                size,
+                mtime,
+                summary.start,
+                summary.end,
+                serde_json::to_string(&summary.totals).unwrap()
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        {
+            let mut statement = transaction.prepare(
+                "INSERT INTO events (file_id, line, time, kind, name, qty, materials)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for event in &events {
+                let (kind, name, qty) = to_row(&event.event);
+                statement.execute(params![
+                    id,
+                    event.line as i64,
+                    event.time,
+                    kind,
+                    name,
+                    qty as i64,
+                    materials_of(&event.event)
+                ])?;
+            }
+        }
+        //ライブで保存したこのログの周回は読み直した周回で置き換える
+        transaction.execute(
+            "DELETE FROM runs WHERE file_id IS NULL AND start >= ?1 AND start <= ?2",
+            params![summary.start, summary.end],
+        )?;
+        for run in &runs {
+            insert_run(&transaction, run, Some(id))?;
+        }
+        transaction.commit()?;
+        Ok(id)
+    }
+    /// 保存済みのファイルの期間内の統計
+    pub fn count(
+        &self,
+        file_id: i64,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+    ) -> rusqlite::Result<InnerStatics> {
+        self.count_where(file_id, statics_address, range, false)
+    }
+    /// 保存済みのファイルの期間内の統計のうち,周回の中で起きたもの
+    pub fn count_in_runs(
+        &self,
+        file_id: i64,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+    ) -> rusqlite::Result<InnerStatics> {
+        self.count_where(file_id, statics_address, range, true)
+    }
+    fn count_where(
+        &self,
+        file_id: i64,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+        in_runs: bool,
+    ) -> rusqlite::Result<InnerStatics> {
+        let mut table = InnerStatics::new();
+        let kind = kind_of(statics_address);
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(if in_runs {
+            "SELECT name, SUM(qty) FROM events
+             WHERE file_id = ?1 AND kind = ?2
+             AND (?3 IS NULL OR time >= ?3) AND (?4 IS NULL OR time < ?4)
+             AND EXISTS (SELECT 1 FROM runs WHERE runs.start <= events.time AND events.time <= runs.exit)
+             GROUP BY name"
+        } else {
+            "SELECT name, SUM(qty) FROM events
+             WHERE file_id = ?1 AND kind = ?2
+             AND (?3 IS NULL OR time >= ?3) AND (?4 IS NULL OR time < ?4)
+             GROUP BY name"
+        })?;
+        let rows = statement.query_map(params![file_id, kind, range.from, range.to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        let dictionary = statics_address.dictionary();
+        for row in rows {
+            let (name, qty) = row?;
+            match dictionary {
+                //tsvの辞書にあるメッセージだけを数える
+                Some(dictionary) => {
+                    if let Some(name) = dictionary.get(&name) {
+                        add_to_table(&mut table, name, qty as isize);
+                    }
+                }
+                None => add_to_table(&mut table, name, qty as isize),
+            }
+        }
+        Ok(table)
+    }
+    /// 保存済みのファイルの期間内のイベントを時刻順に
+    pub fn timeline(
+        &self,
+        file_id: i64,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+    ) -> rusqlite::Result<Vec<(NaiveDateTime, String)>> {
+        let kind = kind_of(statics_address);
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT time, name FROM events
+             WHERE file_id = ?1 AND kind = ?2
+             AND (?3 IS NULL OR time >= ?3) AND (?4 IS NULL OR time < ?4)
+             ORDER BY line",
+        )?;
+        let rows = statement.query_map(params![file_id, kind, range.from, range.to], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect()
+    }
+    /// 保存済みのファイルの期間内の指定した種類のイベント
+    pub fn events(
+        &self,
+        file_id: i64,
+        kinds: &[&'static str],
+        range: &TimeRange,
+    ) -> rusqlite::Result<Vec<TimedEvent>> {
+        let kinds: Vec<String> = kinds.iter().map(|kind| format!("'{}'", kind)).collect();
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "SELECT line, time, kind, name, qty, materials FROM events
+             WHERE file_id = ?1 AND kind IN ({})
+             AND (?2 IS NULL OR time >= ?2) AND (?3 IS NULL OR time < ?3)
+             ORDER BY line",
+            kinds.join(", ")
+        ))?;
+        let rows = statement.query_map(params![file_id, range.from, range.to], |row| {
+            let kind: String = row.get(2)?;
+            Ok(TimedEvent {
+                line: row.get::<_, i64>(0)? as usize,
+                time: row.get(1)?,
+                event: from_row(
+                    &kind,
+                    row.get(3)?,
+                    row.get::<_, i64>(4)? as isize,
+                    row.get(5)?,
+                ),
+            })
+        })?;
+        rows.collect()
+    }
+    /// 保存済みのファイルの期間内の最後の時刻
+    pub fn last_time(
+        &self,
+        file_id: i64,
+        range: &TimeRange,
+    ) -> rusqlite::Result<Option<NaiveDateTime>> {
+        self.connection.lock().unwrap().query_row(
+            "SELECT MAX(time) FROM events WHERE file_id = ?1
+             AND (?2 IS NULL OR time >= ?2) AND (?3 IS NULL OR time < ?3)",
+            params![file_id, range.from, range.to],
+            |row| row.get(0),
+        )
+    }
+    /// 保存済みのファイルのまとめ
+    /// 読み込んだときに数えておいたものを返す
+    pub fn summary(&self, file_id: i64, path: &str) -> rusqlite::Result<SessionSummary> {
+        let mut summary = SessionSummary::new(path, false);
+        let (start, end, totals) = self.connection.lock().unwrap().query_row(
+            "SELECT first_time, last_time, totals FROM files WHERE id = ?1",
+            params![file_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, String>(2)?)),
+        )?;
+        summary.start = start;
+        summary.end = end;
+        if let Ok(totals) = serde_json::from_str::<Vec<isize>>(&totals) {
+            if totals.len() == summary.totals.len() {
+                summary.totals = totals;
+            }
+        }
+        Ok(summary)
+    }
+    /// startからendまでに始まった期間内の周回の数
+    pub fn count_runs(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+        range: &TimeRange,
+    ) -> rusqlite::Result<usize> {
+        self.connection.lock().unwrap().query_row(
+            "SELECT COUNT(*) FROM runs WHERE start >= ?1 AND start <= ?2
+             AND (?3 IS NULL OR start >= ?3) AND (?4 IS NULL OR start < ?4)",
+            params![start, end, range.from, range.to],
+            |row| row.get::<_, i64>(0).map(|count| count as usize),
+        )
+    }
+    /// startからendまでの周回の開始と終了
+    pub fn run_windows(
+        &self,
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    ) -> rusqlite::Result<Vec<(NaiveDateTime, NaiveDateTime)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT start, exit FROM runs WHERE exit >= ?1 AND start <= ?2 ORDER BY start",
+        )?;
+        let rows =
+            statement.query_map(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+    /// 終わった周回を保存する
+    /// 同じ時刻に始まった周回は保存済みとみなす
+    pub fn save_run(&self, run: &DungeonRun) -> rusqlite::Result<()> {
+        insert_run(&self.connection.lock().unwrap(), run, None)
+    }
+    /// 保存済みの周回(新しい順)
+    /// dungeonを指定すればそのダンジョンだけ
+    pub fn runs(&self, dungeon: Option<&str>) -> rusqlite::Result<Vec<DungeonRun>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT dungeon, start, clear, exit, lap_time, statics, abort FROM runs
+             WHERE ?1 IS NULL OR dungeon = ?1
+             ORDER BY start DESC",
+        )?;
+        let rows = statement.query_map(params![dungeon], to_run)?;
+        rows.collect()
+    }
+    /// そのダンジョンで最速の周回
+    pub fn best_run(&self, dungeon: &str) -> rusqlite::Result<Option<DungeonRun>> {
+        self.connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT dungeon, start, clear, exit, lap_time, statics, abort FROM runs
+                 WHERE dungeon = ?1 AND lap_time IS NOT NULL
+                 ORDER BY lap_time LIMIT 1",
+                params![dungeon],
+                to_run,
+            )
+            .optional()
+    }
+    /// ダンジョンごとの最速と平均のラップタイム
+    pub fn lap_records(&self) -> rusqlite::Result<Vec<LapRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT dungeon, COUNT(*), MIN(lap_time), AVG(lap_time) FROM runs
+             WHERE lap_time IS NOT NULL
+             GROUP BY dungeon ORDER BY dungeon",
+        )?;
+        let rows = statement.query_map(params![], |row| {
+            Ok(LapRecord {
+                dungeon: row.get(0)?,
+                runs: row.get::<_, i64>(1)? as usize,
+                best: row.get(2)?,
+                average: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+    /// ダンジョンごとのクリアと中断の回数
+    pub fn clear_rates(&self) -> rusqlite::Result<Vec<ClearRate>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(
+            "SELECT dungeon, COUNT(clear), COUNT(abort) FROM runs
+             GROUP BY dungeon ORDER BY dungeon",
+        )?;
+        let rows = statement.query_map(params![], |row| {
+            let clears = row.get::<_, i64>(1)? as usize;
+            let aborts = row.get::<_, i64>(2)? as usize;
+            Ok(ClearRate {
+                dungeon: row.get(0)?,
+                clears,
+                aborts,
+                rate: clears as f64 / (clears + aborts).max(1) as f64,
+            })
+        })?;
+        rows.collect()
+    }
+    /// 書き込みの終わったログファイルの期間内の統計
+    /// データベースが使えなければその場でログを読む
+    pub fn statics(
+        &self,
+        path: &str,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+    ) -> InnerStatics {
+        match self
+            .ingest(path)
+            .and_then(|id| self.count(id, statics_address, range))
+        {
+            Ok(statics) => statics,
+            Err(error) => {
+                eprintln!("{}", error);
+                let events = read_in_range(path, range);
+                engine_count(&events, 0, statics_address)
+            }
+        }
+    }
+    /// 書き込みの終わったログファイルの期間内のガチャのまわし
+    /// データベースが使えなければその場でログを読む
+    pub fn pulls(&self, path: &str, range: &TimeRange) -> Vec<Pull> {
+        match self
+            .ingest(path)
+            .and_then(|id| self.events(id, &["item_use", "gacha_win"], range))
+        {
+            Ok(events) => engine_gacha_pulls(&events, 0),
+            Err(error) => {
+                eprintln!("{}", error);
+                let events = read_in_range(path, range);
+                engine_gacha_pulls(&events, 0)
+            }
+        }
+    }
+    /// 書き込みの終わったログファイルの期間内の合成
+    /// データベースが使えなければその場でログを読む
+    pub fn syntheses(&self, path: &str, range: &TimeRange) -> Vec<Synthesis> {
+        match self
+            .ingest(path)
+            .and_then(|id| self.events(id, &["lab_success", "lab_failure"], range))
+        {
+            Ok(events) => engine_synthesis(&events, 0),
+            Err(error) => {
+                eprintln!("{}", error);
+                let events = read_in_range(path, range);
+                engine_synthesis(&events, 0)
+            }
+        }
+    }
+    /// 書き込みの終わったログファイルの期間内の移動と,そのログの最後の時刻
+    /// データベースが使えなければその場でログを読む
+    pub fn travels(&self, path: &str, range: &TimeRange) -> Option<(Vec<Travel>, NaiveDateTime)> {
+        let stored = self.ingest(path).and_then(|id| {
+            Ok((
+                self.events(id, &["message"], range)?,
+                self.last_time(id, range)?,
+            ))
+        });
+        let (events, end) = match stored {
+            Ok(stored) => stored,
+            Err(error) => {
+                eprintln!("{}", error);
+                let events = read_in_range(path, range);
+                let end = events.last().map(|event| event.time);
+                (events, end)
+            }
+        };
+        Some((engine_travel(&events, 0), end?))
+    }
+    /// 書き込みの終わったログファイルの期間内のイベントの時刻と名前
+    /// データベースが使えなければその場でログを読む
+    pub fn events_of(
+        &self,
+        path: &str,
+        statics_address: StaticsAddress,
+        range: &TimeRange,
+    ) -> Vec<(NaiveDateTime, String)> {
+        match self
+            .ingest(path)
+            .and_then(|id| self.timeline(id, statics_address, range))
+        {
+            Ok(timeline) => timeline,
+            Err(error) => {
+                eprintln!("{}", error);
+                read_in_range(path, range)
+                    .into_iter()
+                    .filter_map(|event| {
+                        let (name, _) = event.event.count(statics_address)?;
+                        Some((event.time, name.to_string()))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// ログファイルから読んだ周回(file_idあり)は同じ時刻に始まった周回を置き換える
+/// ライブで記録した周回は,同じ時刻に始まった周回があれば何もしない
+fn insert_run(
+    connection: &Connection,
+    run: &DungeonRun,
+    file_id: Option<i64>,
+) -> rusqlite::Result<()> {
+    let statics = serde_json::to_string(&run.statics).unwrap();
+    let sql = match file_id {
+        Some(_) => {
+            "INSERT OR REPLACE INTO runs
+             (dungeon, start, clear, exit, lap_time, statics, abort, file_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        }
+        None => {
+            "INSERT OR IGNORE INTO runs
+             (dungeon, start, clear, exit, lap_time, statics, abort, file_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+        }
+    };
+    connection.execute(
+        sql,
+        params![
+            run.dungeon,
+            run.start,
+            run.clear,
+            run.exit,
+            run.lap_time.map(|lap_time| lap_time.num_seconds()),
+            statics,
+            run.abort.map(|abort| abort.as_str()),
+            file_id
+        ],
+    )?;
+    Ok(())
+}
+
+/// 分類の仕方かルールが保存したときと違えば,保存済みのイベントを捨てる
+fn discard_stale(connection: &Connection) -> rusqlite::Result<()> {
+    let version = format!("{}\n{}", PARSER_VERSION, rules::current().version());
+    let stored: Option<String> = connection
+        .query_row(
+            "SELECT value FROM meta WHERE key = 'parser'",
+            params![],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if stored.as_deref() != Some(version.as_str()) {
+        connection.execute_batch("DELETE FROM files")?;
+        connection.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('parser', ?1)",
+            params![version],
+        )?;
+    }
+    Ok(())
+}
+
+/// データベースを使わずに期間内のイベントを読む
+/// 読めなければ空とする
+fn read_in_range(path: &str, range: &TimeRange) -> Vec<TimedEvent> {
+    match read_events(path) {
+        Ok(events) => events
+            .into_iter()
+            .filter(|event| range.contains(event.time))
+            .collect(),
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            Vec::new()
+        }
+    }
+}
+
+/// サイズと更新日時(ミリ秒)
+fn file_stamp(path: &str) -> (i64, i64) {
+    match std::fs::metadata(path) {
+        Ok(metadata) => {
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_millis() as i64)
+                .unwrap_or_default();
+            (metadata.len() as i64, mtime)
+        }
+        Err(_) => (0, 0),
+    }
+}
+
+/// ダンジョンごとのラップタイム(秒)
+#[derive(Debug, Clone, Serialize)]
+pub struct LapRecord {
+    pub dungeon: Option<String>,
+    pub runs: usize,
+    pub best: i64,
+    pub average: f64,
+}
+
+/// ダンジョンごとのクリア率
+#[derive(Debug, Clone, Serialize)]
+pub struct ClearRate {
+    pub dungeon: Option<String>,
+    pub clears: usize,
+    pub aborts: usize,
+    /// クリアと中断に占めるクリアの割合
+    pub rate: f64,
+}
+
+/// SELECT dungeon, start, clear, exit, lap_time, statics の1行
+fn to_run(row: &rusqlite::Row) -> rusqlite::Result<DungeonRun> {
+    let statics: String = row.get(5)?;
+    Ok(DungeonRun {
+        dungeon: row.get(0)?,
+        start: row.get(1)?,
+        clear: row.get(2)?,
+        exit: row.get(3)?,
+        lap_time: row.get::<_, Option<i64>>(4)?.map(chrono::Duration::seconds),
+        abort: row
+            .get::<_, Option<String>>(6)?
+            .and_then(|abort| abort.parse().ok()),
+        statics: serde_json::from_str::<RunStatics>(&statics).unwrap_or_default(),
+    })
+}
+
+/// (kind,name,qty)
+fn to_row(event: &LogEvent) -> (&'static str, &str, isize) {
+    match event {
+        LogEvent::ItemGet { name, qty } => ("item_get", name, *qty),
+        LogEvent::PartGet { name } => ("part_get", name, 1),
+        LogEvent::ItemUse { name } => ("item_use", name, 1),
+        LogEvent::Kill { name } => ("kill", name, 1),
+        LogEvent::GachaWin { name } => ("gacha_win", name, 1),
+        LogEvent::RareGachaWin { name } => ("rare_gacha_win", name, 1),
+        LogEvent::LabSuccess { name, qty, .. } => ("lab_success", name, *qty),
+        LogEvent::LabFailure { .. } => ("lab_failure", "", 1),
+        LogEvent::DungeonClear => ("dungeon_clear", "", 1),
+        LogEvent::DungeonReward { name, qty } => ("dungeon_reward", name, *qty),
+        LogEvent::DungeonSell { name, qty } => ("dungeon_sell", name, *qty),
+        LogEvent::SellTotal { amount } => ("sell_total", "", *amount),
+        LogEvent::FloorGate { name } => ("floor_gate", name, 1),
+        LogEvent::ScriptedMessage { key } => ("message", key, 1),
+    }
+}
+
+/// 合成の材料はJSONで保存する
+fn materials_of(event: &LogEvent) -> Option<String> {
+    match event {
+        LogEvent::LabSuccess { materials, .. } | LogEvent::LabFailure { materials } => {
+            serde_json::to_string(materials).ok()
+        }
+        _ => None,
+    }
+}
+
+/// to_rowの逆
+fn from_row(kind: &str, name: String, qty: isize, materials: Option<String>) -> LogEvent {
+    let materials = || {
+        materials
+            .as_deref()
+            .and_then(|materials| serde_json::from_str(materials).ok())
+            .unwrap_or_default()
+    };
+    match kind {
+        "item_get" => LogEvent::ItemGet { name, qty },
+        "part_get" => LogEvent::PartGet { name },
+        "item_use" => LogEvent::ItemUse { name },
+        "kill" => LogEvent::Kill { name },
+        "gacha_win" => LogEvent::GachaWin { name },
+        "rare_gacha_win" => LogEvent::RareGachaWin { name },
+        "lab_success" => LogEvent::LabSuccess {
+            name,
+            qty,
+            materials: materials(),
+        },
+        "lab_failure" => LogEvent::LabFailure {
+            materials: materials(),
+        },
+        "dungeon_clear" => LogEvent::DungeonClear,
+        "dungeon_reward" => LogEvent::DungeonReward { name, qty },
+        "dungeon_sell" => LogEvent::DungeonSell { name, qty },
+        "sell_total" => LogEvent::SellTotal { amount: qty },
+        "floor_gate" => LogEvent::FloorGate { name },
+        _ => LogEvent::ScriptedMessage { key: name },
+    }
+}
+
+/// カウンタが数えるイベントの種類
+fn kind_of(statics_address: StaticsAddress) -> &'static str {
+    match statics_address {
+        StaticsAddress::Item => "item_get",
+        StaticsAddress::Parts => "part_get",
+        StaticsAddress::ItemUse => "item_use",
+        StaticsAddress::Kill => "kill",
+        StaticsAddress::Gacha => "gacha_win",
+        StaticsAddress::RareGacha => "rare_gacha_win",
+        StaticsAddress::Lab => "lab_success",
+        StaticsAddress::DungeonReward => "dungeon_reward",
+        StaticsAddress::DungeonSell => "dungeon_sell",
+        StaticsAddress::Burst
+        | StaticsAddress::Mission
+        | StaticsAddress::DungeonClear
+        | StaticsAddress::Shuttle => "message",
+    }
+}
+
+#[cfg(test)]
+mod store_test {
+    use chrono::NaiveDate;
+    use encoding::all::WINDOWS_31J;
+    use encoding::{EncoderTrap, Encoding};
+
+    use crate::dungeon_state_machine::{AbortReason, DungeonRun, RunStatics};
+    use crate::statics_address::StaticsAddress;
+    use crate::store::EventStore;
+    use crate::time_range::TimeRange;
+
+    #[test]
+    fn test_ingest_only_changed_files() {
+        let path = std::env::temp_dir().join("c21_counter_rs_store_test.txt");
+        let path = path.to_str().unwrap();
+        let line = "2021-01-01\t00:00:00\t[INFO]\t[リペアパック] を 2個 取得した！\r\n";
+        let bytes = WINDOWS_31J.encode(line, EncoderTrap::Strict).unwrap();
+        std::fs::write(path, &bytes).unwrap();
+
+        let store = EventStore::open_in_memory().unwrap();
+        let id = store.ingest(path).unwrap();
+        assert_eq!(store.ingest(path).unwrap(), id);
+        let statics = store
+            .count(id, StaticsAddress::Item, &TimeRange::default())
+            .unwrap();
+        assert_eq!(statics.get("[リペアパック]"), Some(&2));
+
+        //追記されたら読み直す
+        let bytes = WINDOWS_31J
+            .encode(&line.repeat(2), EncoderTrap::Strict)
+            .unwrap();
+        std::fs::write(path, &bytes).unwrap();
+        let id = store.ingest(path).unwrap();
+        let statics = store
+            .count(id, StaticsAddress::Item, &TimeRange::default())
+            .unwrap();
+        assert_eq!(statics.get("[リペアパック]"), Some(&4));
+        //期間外
+        let range = TimeRange {
+            from: Some(
+                NaiveDate::from_ymd_opt(2021, 1, 2)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap(),
+            ),
+            ..TimeRange::default()
+        };
+        assert!(store
+            .count(id, StaticsAddress::Item, &range)
+            .unwrap()
+            .is_empty());
+        let summary = store.summary(id, path).unwrap();
+        assert_eq!(summary.file, "c21_counter_rs_store_test.txt");
+        assert_eq!(summary.totals[0], 4);
+        assert_eq!(summary.duration(), Some(chrono::Duration::zero()));
+
+        //合成の材料も保存する
+        let lab = "2021-01-01\t00:00:00\t[INFO]\t合成に失敗しました。\r\n有機的な破片 × 4\r\n";
+        let bytes = WINDOWS_31J.encode(lab, EncoderTrap::Strict).unwrap();
+        std::fs::write(path, &bytes).unwrap();
+        let syntheses = store.syntheses(path, &TimeRange::default());
+        assert_eq!(syntheses.len(), 1);
+        assert_eq!(syntheses[0].product, None);
+        assert_eq!(
+            syntheses[0].materials,
+            vec![("有機的な破片".to_string(), 4)]
+        );
+        //分類の仕方が変わったら読み直す
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "UPDATE events SET materials = NULL;
+                 UPDATE meta SET value = 'old' WHERE key = 'parser';",
+            )
+            .unwrap();
+        let syntheses = store.syntheses(path, &TimeRange::default());
+        assert_eq!(syntheses[0].materials.len(), 1);
+        std::fs::remove_file(path).unwrap();
+        //消えたファイルは飛ばす
+        assert!(store.ingest(path).is_err());
+        assert!(store
+            .statics(path, StaticsAddress::Item, &TimeRange::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_ingest_backfills_runs() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/dungeon_runs.txt");
+        let store = EventStore::open_in_memory().unwrap();
+        store.ingest(path).unwrap();
+        let runs = store.runs(None).unwrap();
+        //中断した3周も含む
+        assert_eq!(runs.len(), 6);
+        let first = runs.last().unwrap();
+        assert_eq!(first.dungeon.as_deref(), Some("ポルド遺跡"));
+        //1周あたりの分子と分母
+        let id = store.ingest(path).unwrap();
+        let range = TimeRange::default();
+        let summary = store.summary(id, path).unwrap();
+        let (start, end) = (summary.start.unwrap(), summary.end.unwrap());
+        assert_eq!(store.count_runs(start, end, &range).unwrap(), 6);
+        let in_runs = store
+            .count_in_runs(id, StaticsAddress::Item, &range)
+            .unwrap();
+        assert_eq!(in_runs.get("[リペアパック]"), Some(&5));
+        assert_eq!(store.run_windows(start, end).unwrap()[0].0, first.start);
+        //ファイルを捨てると周回も消える
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch("DELETE FROM files")
+            .unwrap();
+        assert!(store.runs(None).unwrap().is_empty());
+        //ライブで記録した周回は読み直した周回で置き換える
+        let live = |minute| DungeonRun {
+            dungeon: Some("ライブ".to_string()),
+            start: start + chrono::Duration::minutes(minute),
+            ..first.clone()
+        };
+        store.save_run(&live(1)).unwrap();
+        store.save_run(&live(5)).unwrap();
+        store.ingest(path).unwrap();
+        let runs = store.runs(None).unwrap();
+        assert_eq!(runs.len(), 6);
+        assert!(runs
+            .iter()
+            .all(|run| run.dungeon.as_deref() != Some("ライブ")));
+        //分類の仕方が変わったら古い周回は残さない
+        store
+            .connection
+            .lock()
+            .unwrap()
+            .execute_batch(
+                "UPDATE runs SET dungeon = '古い';
+                 UPDATE meta SET value = 'old' WHERE key = 'parser';",
+            )
+            .unwrap();
+        store.ingest(path).unwrap();
+        let runs = store.runs(None).unwrap();
+        assert_eq!(runs.len(), 6);
+        assert_eq!(runs[5].dungeon.as_deref(), Some("ポルド遺跡"));
+    }
+
+    #[test]
+    fn test_lap_records() {
+        let store = EventStore::open_in_memory().unwrap();
+        let time = |minute| {
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, minute, 0)
+                .unwrap()
+        };
+        for (start, lap) in [(0, 5), (10, 3), (20, 4)] {
+            let run = DungeonRun {
+                dungeon: Some("ダンジョン".to_string()),
+                start: time(start),
+                clear: Some(time(start + lap)),
+                exit: time(start + lap + 1),
+                lap_time: Some(chrono::Duration::minutes(lap as i64)),
+                abort: None,
+                statics: RunStatics::default(),
+            };
+            store.save_run(&run).unwrap();
+            //同じ周回は1回だけ
+            store.save_run(&run).unwrap();
+        }
+        let runs = store.runs(Some("ダンジョン")).unwrap();
+        assert_eq!(runs.len(), 3);
+        assert_eq!(runs[0].start, time(20));
+        assert!(store.runs(Some("不明")).unwrap().is_empty());
+        let records = store.lap_records().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].runs, 3);
+        assert_eq!(records[0].best, 180);
+        assert_eq!(records[0].average, 240.0);
+        let best = store.best_run("ダンジョン").unwrap().unwrap();
+        assert_eq!(best.start, time(10));
+
+        //中断した周回はラップタイムに入らずクリア率に入る
+        store
+            .save_run(&DungeonRun {
+                dungeon: Some("ダンジョン".to_string()),
+                start: time(30),
+                clear: None,
+                exit: time(32),
+                lap_time: None,
+                abort: Some(AbortReason::EscapeGate),
+                statics: RunStatics::default(),
+            })
+            .unwrap();
+        assert_eq!(
+            store.runs(Some("ダンジョン")).unwrap()[0].abort,
+            Some(AbortReason::EscapeGate)
+        );
+        assert_eq!(store.lap_records().unwrap()[0].runs, 3);
+        let rates = store.clear_rates().unwrap();
+        assert_eq!((rates[0].clears, rates[0].aborts), (3, 1));
+        assert_eq!(rates[0].rate, 0.75);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use encoding::all::WINDOWS_31J;
+use encoding::DecoderTrap;
+use encoding::Encoding;
+
+/// ローテーションの検出に使う先頭のバイト数
+const HEAD_LEN: u64 = 64;
+
+/// ログファイルの読み込み位置を覚えておき,追記されたバイトだけをデコードする
+/// Shift_JISの2バイト目に改行(0x0A)は現れないので,改行までを1行として切り出せば
+/// マルチバイト文字の途中で切れることはない
+pub struct LogTailer {
+    path: PathBuf,
+    offset: u64,
+    //改行で終わっていない末尾
+    pending: Vec<u8>,
+    //ファイルの先頭
+    head: Vec<u8>,
+}
+
+/// 追記された行
+pub struct Tail {
+    /// 切り詰めかローテーションを検出して先頭から読み直した
+    pub reset: bool,
+    pub lines: Vec<String>,
+}
+
+impl LogTailer {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            offset: 0,
+            pending: Vec::new(),
+            head: Vec::new(),
+        }
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// 読み込み済みのバイト数
+    #[cfg(test)]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    /// 前回からの追記分を読み込む
+    pub fn poll(&mut self) -> std::io::Result<Tail> {
+        let mut file = File::open(&self.path)?;
+        let len = file.metadata()?.len();
+        let mut head = Vec::with_capacity(HEAD_LEN as usize);
+        (&mut file).take(HEAD_LEN).read_to_end(&mut head)?;
+        //先頭が書き換わっていれば別のファイル
+        let common = self.head.len().min(head.len());
+        let rotated = self.head[..common] != head[..common];
+        let reset = len < self.offset || rotated;
+        if reset {
+            self.offset = 0;
+            self.pending.clear();
+        }
+        self.head = head;
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)?;
+        self.offset += appended.len() as u64;
+        self.pending.extend_from_slice(&appended);
+
+        let lines = match self.pending.iter().rposition(|byte| *byte == b'\n') {
+            Some(end) => {
+                let rest = self.pending.split_off(end + 1);
+                let complete = std::mem::replace(&mut self.pending, rest);
+                decode_lines(&complete)
+            }
+            None => Vec::new(),
+        };
+        Ok(Tail { reset, lines })
+    }
+}
+
+fn decode_lines(bytes: &[u8]) -> Vec<String> {
+    let text = WINDOWS_31J.decode(bytes, DecoderTrap::Ignore).unwrap();
+    let mut lines: Vec<String> = text
+        .split('\n')
+        .map(|line| line.trim_end_matches('\r').to_string())
+        .collect();
+    //最後の改行の後ろは空
+    lines.pop();
+    lines
+}
+
+#[cfg(test)]
+mod tailer_test {
+    use std::io::Write;
+
+    use encoding::all::WINDOWS_31J;
+    use encoding::{EncoderTrap, Encoding};
+
+    use crate::tailer::LogTailer;
+
+    #[test]
+    fn test_tail_partial_line_and_truncate() {
+        let path = std::env::temp_dir().join("c21_counter_rs_tailer_test.txt");
+        let line = WINDOWS_31J
+            .encode(
+                "2021-01-01\t00:00:00\t[INFO]\t[リペアパック] を使用した！\r\n",
+                EncoderTrap::Strict,
+            )
+            .unwrap();
+        let mut file = std::fs::File::create(&path).unwrap();
+        let mut tailer = LogTailer::new(&path);
+        //マルチバイト文字の途中まで書き込む
+        file.write_all(&line[..30]).unwrap();
+        file.flush().unwrap();
+        let tail = tailer.poll().unwrap();
+        assert!(!tail.reset);
+        assert!(tail.lines.is_empty());
+
+        file.write_all(&line[30..]).unwrap();
+        file.write_all(&line).unwrap();
+        file.flush().unwrap();
+        let tail = tailer.poll().unwrap();
+        assert_eq!(tail.lines.len(), 2);
+        assert_eq!(
+            tail.lines[0],
+            "2021-01-01\t00:00:00\t[INFO]\t[リペアパック] を使用した！"
+        );
+        assert_eq!(tailer.offset(), line.len() as u64 * 2);
+
+        //切り詰め
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(&line).unwrap();
+        file.flush().unwrap();
+        let tail = tailer.poll().unwrap();
+        assert!(tail.reset);
+        assert_eq!(tail.lines.len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
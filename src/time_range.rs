@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+
+/// 統計を絞り込むクエリ
+/// ?from=2021-01-01&to=2021-01-07 ?today ?last_7_days ?session=ログファイル名
+#[derive(Debug, Deserialize, Default)]
+pub struct RangeQuery {
+    pub(crate) from: Option<String>,
+    pub(crate) to: Option<String>,
+    pub(crate) today: Option<String>,
+    pub(crate) last_7_days: Option<String>,
+    pub(crate) session: Option<String>,
+}
+
+/// 統計に含めるログの範囲
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TimeRange {
+    /// この時刻以降
+    pub from: Option<NaiveDateTime>,
+    /// この時刻より前
+    pub to: Option<NaiveDateTime>,
+    /// ログファイル名
+    pub session: Option<String>,
+}
+
+impl TimeRange {
+    /// todayとlast_7_daysはnowの日付を基準にする
+    /// 始まりを決めるfrom,today,last_7_daysは1つだけ指定できる
+    pub fn from_query(query: &RangeQuery, now: NaiveDateTime) -> Result<Self, String> {
+        let mut range = Self::default();
+        //フォームの空欄は指定なし
+        let given = |text: &Option<String>| text.clone().filter(|text| !text.is_empty());
+        let starts = [
+            given(&query.from).is_some(),
+            query.today.is_some(),
+            query.last_7_days.is_some(),
+        ];
+        if starts.iter().filter(|start| **start).count() > 1 {
+            return Err("from, today, last_7_days のどれか1つだけを指定してください".to_string());
+        }
+        if let Some(from) = given(&query.from) {
+            range.from = Some(parse_time(&from, false)?);
+        }
+        if let Some(to) = given(&query.to) {
+            range.to = Some(parse_time(&to, true)?);
+        }
+        let midnight = now
+            .date()
+            .and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap());
+        if query.today.is_some() {
+            range.from = Some(midnight);
+        }
+        if query.last_7_days.is_some() {
+            range.from = Some(midnight - Duration::days(6));
+        }
+        range.session = given(&query.session);
+        Ok(range)
+    }
+    /// 絞り込みなし
+    pub fn is_all(&self) -> bool {
+        *self == Self::default()
+    }
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        self.from.is_none_or(|from| from <= time) && self.to.is_none_or(|to| time < to)
+    }
+    /// ログファイルが対象に含まれるか
+    pub fn includes_file(&self, path: &str) -> bool {
+        match self.session {
+            Some(ref session) => Path::new(path)
+                .file_name()
+                .is_some_and(|name| name == session.as_str()),
+            None => true,
+        }
+    }
+    /// 見出しに付ける説明
+    pub fn label(&self) -> Option<String> {
+        if self.is_all() {
+            return None;
+        }
+        let format = |time: Option<NaiveDateTime>| {
+            time.map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_default()
+        };
+        let mut label = Vec::new();
+        if self.from.is_some() || self.to.is_some() {
+            label.push(format!("{} 〜 {}", format(self.from), format(self.to)));
+        }
+        if let Some(ref session) = self.session {
+            label.push(session.clone());
+        }
+        Some(label.join(" "))
+    }
+}
+
+/// 日付だけならその日の始まり,toならその次の日の始まりとする
+fn parse_time(text: &str, to: bool) -> Result<NaiveDateTime, String> {
+    for format in &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(time);
+        }
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map_err(|error| format!("{}: {}", text, error))?;
+    let date = if to { date + Duration::days(1) } else { date };
+    Ok(date.and_time(NaiveTime::from_hms_opt(0, 0, 0).unwrap()))
+}
+
+#[cfg(test)]
+mod time_range_test {
+    use actix_web::web::Query;
+    use chrono::NaiveDate;
+
+    use crate::time_range::{RangeQuery, TimeRange};
+
+    #[test]
+    fn test_from_query() {
+        let now = NaiveDate::from_ymd_opt(2021, 1, 8)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let query = Query::<RangeQuery>::from_query("from=2021-01-01&to=2021-01-07").unwrap();
+        let range = TimeRange::from_query(&query, now).unwrap();
+        let day = |day| {
+            NaiveDate::from_ymd_opt(2021, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        assert_eq!(range.from, Some(day(1)));
+        //toの日付は含む
+        assert_eq!(range.to, Some(day(8)));
+        assert!(range.contains(day(7)));
+        assert!(!range.contains(day(8)));
+
+        let query = Query::<RangeQuery>::from_query("last_7_days").unwrap();
+        let range = TimeRange::from_query(&query, now).unwrap();
+        assert_eq!(range.from, Some(day(2)));
+        assert_eq!(range.to, None);
+
+        let query = Query::<RangeQuery>::from_query("from=yesterday").unwrap();
+        assert!(TimeRange::from_query(&query, now).is_err());
+
+        //fromを黙って上書きしない
+        let query = Query::<RangeQuery>::from_query("from=2021-01-01&today").unwrap();
+        assert!(TimeRange::from_query(&query, now).is_err());
+        let query = Query::<RangeQuery>::from_query("today&last_7_days").unwrap();
+        assert!(TimeRange::from_query(&query, now).is_err());
+        //フォームの空欄のfromは指定なし
+        let query = Query::<RangeQuery>::from_query("from=&today").unwrap();
+        assert_eq!(
+            TimeRange::from_query(&query, now).unwrap().from,
+            Some(day(8))
+        );
+    }
+}
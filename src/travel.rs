@@ -0,0 +1,229 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::engines::{gate_destination, jump_destination, GateType};
+use crate::log_event::{LogEvent, TimedEvent};
+use crate::statics_address::StaticsAddress;
+use crate::utils::{format_seconds, format_time};
+
+/// 移動の手段
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum TravelKind {
+    Jump,
+    Gate,
+    /// ダンジョンから出るだけで場所は変わらない
+    EscapeGate,
+    Shuttle,
+}
+
+impl TravelKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TravelKind::Jump => "ジャンプ",
+            TravelKind::Gate => "ゲート",
+            TravelKind::EscapeGate => "エスケープゲート",
+            TravelKind::Shuttle => "シャトル",
+        }
+    }
+}
+
+/// 1回の移動
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Travel {
+    pub time: NaiveDateTime,
+    pub kind: TravelKind,
+    /// 行き先 エスケープゲートはその時いた場所
+    pub place: Option<String>,
+}
+
+/// シャトルの到着をshuttle.tsvで照合する
+/// ゲートとジャンプの行もshuttle.tsvに載っているので先に除く
+fn shuttle_arrival(event: &LogEvent) -> Option<String> {
+    match event {
+        LogEvent::ScriptedMessage { key } => {
+            let value = StaticsAddress::Shuttle.dictionary()?.get(key)?;
+            //a1,ポイーン（ポインドラ）
+            let place = value
+                .split_once(',')
+                .map_or(value.as_str(), |(_, place)| place);
+            Some(place.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// ジャンプ,ゲート,エスケープゲート,シャトルの到着を順に並べる
+pub fn engine_travel(events: &[TimedEvent], from: usize) -> Vec<Travel> {
+    let mut travels: Vec<Travel> = Vec::new();
+    if from > events.len() {
+        return travels;
+    }
+    for event in &events[from..] {
+        let (kind, place) = if let Some(place) = jump_destination(&event.event) {
+            (TravelKind::Jump, Some(place))
+        } else if let Some((place, gate_type)) = gate_destination(&event.event) {
+            match gate_type {
+                GateType::Any => (TravelKind::Gate, Some(place)),
+                GateType::Escape => (
+                    TravelKind::EscapeGate,
+                    travels.last().and_then(|travel| travel.place.clone()),
+                ),
+            }
+        } else if let Some(place) = shuttle_arrival(&event.event) {
+            (TravelKind::Shuttle, Some(place))
+        } else {
+            continue;
+        };
+        travels.push(Travel {
+            time: event.time,
+            kind,
+            place,
+        });
+    }
+    travels
+}
+
+/// 移動の1行
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct TravelRow {
+    pub time: String,
+    pub kind: TravelKind,
+    pub place: Option<String>,
+}
+
+/// 場所ごとの訪問回数と滞在時間
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct PlaceStat {
+    pub place: String,
+    pub visits: usize,
+    /// 秒
+    pub seconds: i64,
+}
+
+impl PlaceStat {
+    pub fn stay(&self) -> String {
+        format_seconds(self.seconds)
+    }
+}
+
+/// 移動の履歴と場所ごとのまとめ
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct TravelReport {
+    /// 時刻順
+    pub timeline: Vec<TravelRow>,
+    /// 訪問回数の多い順
+    pub places: Vec<PlaceStat>,
+}
+
+impl TravelReport {
+    /// ログファイルごとの移動と,そのログの最後の時刻
+    /// 滞在は次の移動かログの終わりまでとする
+    pub fn new(sessions: &[(Vec<Travel>, NaiveDateTime)]) -> Self {
+        let mut report = Self::default();
+        for (travels, end) in sessions {
+            let mut current: Option<&str> = None;
+            for (index, travel) in travels.iter().enumerate() {
+                report.timeline.push(TravelRow {
+                    time: format_time(Some(travel.time)),
+                    kind: travel.kind,
+                    place: travel.place.clone(),
+                });
+                let place = match travel.place {
+                    Some(ref place) => place.as_str(),
+                    None => {
+                        current = None;
+                        continue;
+                    }
+                };
+                let until = travels.get(index + 1).map_or(*end, |next| next.time);
+                let stat = match report
+                    .places
+                    .iter_mut()
+                    .position(|stat| stat.place == place)
+                {
+                    Some(position) => &mut report.places[position],
+                    None => {
+                        report.places.push(PlaceStat {
+                            place: place.to_string(),
+                            visits: 0,
+                            seconds: 0,
+                        });
+                        report.places.last_mut().unwrap()
+                    }
+                };
+                if current != Some(place) {
+                    stat.visits += 1;
+                }
+                stat.seconds += (until - travel.time).num_seconds().max(0);
+                current = Some(place);
+            }
+        }
+        report.places.sort_by(|a, b| {
+            b.visits
+                .cmp(&a.visits)
+                .then_with(|| b.seconds.cmp(&a.seconds))
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod travel_test {
+    use chrono::NaiveDate;
+
+    use crate::log_event::{LogEvent, TimedEvent};
+    use crate::travel::{engine_travel, TravelKind, TravelReport};
+
+    #[test]
+    fn test_travel() {
+        let time = |minute| {
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, minute, 0)
+                .unwrap()
+        };
+        let message = |minute, key: &str| TimedEvent {
+            line: minute as usize,
+            time: time(minute),
+            event: LogEvent::ScriptedMessage {
+                key: key.to_string(),
+            },
+        };
+        let events = vec![
+            message(
+                0,
+                "<シャトル乗務員>\\t当機はただいまポイーン星ポインドラ宇宙港に到着いたしました。",
+            ),
+            message(10, "[INFO]\\t[ポルドジャンプ]を使用した！"),
+            message(20, "[INFO]\\t[エスケープゲート]を使用した！"),
+            message(30, "[INFO]\\t[ギガスゲート★1]を使用した！"),
+            //チャットで同じ文字列を書いても移動しない
+            message(35, "<プレイヤーA>\\t[ポルドジャンプ]を使用した！"),
+            message(40, "[INFO]\\t[ポルドジャンプ]を使用した！"),
+        ];
+        let travels = engine_travel(&events, 0);
+        let kinds: Vec<TravelKind> = travels.iter().map(|travel| travel.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TravelKind::Shuttle,
+                TravelKind::Jump,
+                TravelKind::EscapeGate,
+                TravelKind::Gate,
+                TravelKind::Jump
+            ]
+        );
+        assert_eq!(travels[0].place.as_deref(), Some("ポイーン（ポインドラ）"));
+        assert_eq!(travels[2].place.as_deref(), Some("ポルド"));
+        assert_eq!(travels[3].place.as_deref(), Some("ギガス★1"));
+
+        let report = TravelReport::new(&[(travels, time(50))]);
+        assert_eq!(report.timeline.len(), 5);
+        let poldo = &report.places[0];
+        assert_eq!(poldo.place, "ポルド");
+        //エスケープゲートは同じ場所にとどまる
+        assert_eq!(poldo.visits, 2);
+        assert_eq!(poldo.seconds, 30 * 60);
+        assert_eq!(poldo.stay(), "0:30:00");
+    }
+}
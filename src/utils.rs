@@ -3,14 +3,13 @@ use std::fs;
 use std::io::Read;
 use std::path::Path;
 
+use chrono::NaiveDateTime;
 use encoding::all::WINDOWS_31J;
 use encoding::DecoderTrap;
 use encoding::Encoding;
 
-use crate::engines::{
-    engine_get_info, engine_get_text, engine_get_text2, engine_get_text3, InnerStatics,
-};
-use chrono::NaiveDateTime;
+use crate::engines::InnerStatics;
+use crate::log_event::{EventParser, TimedEvent};
 
 pub enum SortTarget {
     NAME,
@@ -30,25 +29,13 @@ pub fn sort(vec: &mut Vec<(String, isize)>, target: SortTarget, invert: bool) {
     }
 }
 
-pub fn read_from_file<P: AsRef<Path>>(path: P) -> (Vec<NaiveDateTime>, Vec<String>) {
-    let content = fs::read(path).unwrap();
-    let content = content.as_slice();
-    let content = WINDOWS_31J.decode(content, DecoderTrap::Ignore).unwrap();
-    engine_get_text(&content)
-}
-
-pub fn read_from_file2<P: AsRef<Path>>(path: P) -> Vec<String> {
-    let content = fs::read(path).unwrap();
-    let content = content.as_slice();
-    let content = WINDOWS_31J.decode(content, DecoderTrap::Ignore).unwrap();
-    engine_get_info(engine_get_text2(&content))
-}
-
-pub fn read_from_file3<P: AsRef<Path>>(path: P) -> Vec<String> {
-    let content = fs::read(path).unwrap();
+/// ログファイルを読み込んでイベントに分類する
+/// 走査してから読むまでの間にファイルが消えることがあるのでエラーを返す
+pub fn read_events<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<TimedEvent>> {
+    let content = fs::read(path)?;
     let content = content.as_slice();
     let content = WINDOWS_31J.decode(content, DecoderTrap::Ignore).unwrap();
-    engine_get_text3(&content)
+    Ok(EventParser::new().parse(&content))
 }
 
 pub fn connect_hashmap(map0: InnerStatics, map1: InnerStatics) -> InnerStatics {
@@ -75,20 +62,40 @@ pub fn hashmap_to_vec(map: &InnerStatics) -> Vec<(String, isize)> {
     }
     vector
 }
-pub fn load_tsv<P: AsRef<Path>>(path: P) -> HashMap<String, String> {
+/// 表示用の時刻
+pub fn format_time(time: Option<NaiveDateTime>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// 秒数を時:分:秒にする
+pub fn format_seconds(seconds: i64) -> String {
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+pub fn load_tsv<P: AsRef<Path>>(path: P) -> std::io::Result<HashMap<String, String>> {
     let mut map = HashMap::new();
-    let mut file = fs::File::open(path).unwrap();
+    let mut file = fs::File::open(path)?;
     let mut string = String::new();
-    file.read_to_string(&mut string).unwrap();
+    file.read_to_string(&mut string)?;
     let iter = string.split('\n');
     for line in iter {
         let mut iter = line.split('\t');
-        let key = iter.next().unwrap().to_string();
+        let key = iter.next().unwrap_or_default().to_string();
         // let key= key.replace("\\t","\t");
-        let value = iter.next().unwrap().to_string();
+        //タブのない行(末尾の空行など)は飛ばす
+        let value = match iter.next() {
+            Some(value) => value.to_string(),
+            None => continue,
+        };
         #[cfg(dewbug_assertions)]
         println!("key :{} value:{}", key, value);
         map.insert(key, value);
     }
-    map
+    Ok(map)
 }
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use notify::event::ModifyKind;
+use notify::{Config, Event, EventKind, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// chatフォルダのログファイル
+#[derive(Clone, Debug, Default)]
+pub struct ChatFiles {
+    /// 書き込み中のログ(最も新しいもの)
+    pub active: Option<String>,
+    /// 書き込みの終わったログ(古い順)
+    pub finished: Vec<String>,
+}
+
+impl ChatFiles {
+    /// chatフォルダを走査する
+    /// 走査中に消えたファイルは無視する
+    pub fn scan<P: AsRef<Path>>(chat_dir: P) -> Self {
+        let mut paths: Vec<(String, SystemTime)> = match std::fs::read_dir(chat_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let metadata = entry.metadata().ok()?;
+                    if !metadata.is_file() {
+                        return None;
+                    }
+                    let modified = metadata.modified().ok()?;
+                    Some((entry.path().to_str()?.to_string(), modified))
+                })
+                .collect(),
+            Err(error) => {
+                eprintln!("{}", error);
+                Vec::new()
+            }
+        };
+        paths.sort_by_key(|path| path.1);
+        let active = paths.pop().map(|path| path.0);
+        Self {
+            active,
+            finished: paths.into_iter().map(|path| path.0).collect(),
+        }
+    }
+}
+
+/// chatフォルダを監視して書き込み中のログと書き込みの終わったログを把握する
+/// inotifyなどの通知が使えないときはポーリングする
+pub struct ChatWatcher {
+    chat_dir: PathBuf,
+    files: Arc<RwLock<ChatFiles>>,
+    watcher: Mutex<Option<Box<dyn Watcher + Send>>>,
+}
+
+impl ChatWatcher {
+    pub fn new<P: AsRef<Path>>(chat_dir: P) -> Self {
+        let chat_dir = chat_dir.as_ref().to_path_buf();
+        let files = Arc::new(RwLock::new(ChatFiles::scan(&chat_dir)));
+        let watcher = watch(&chat_dir, &files);
+        Self {
+            chat_dir,
+            files,
+            watcher: Mutex::new(watcher),
+        }
+    }
+    pub fn chat_dir(&self) -> &Path {
+        &self.chat_dir
+    }
+    pub fn files(&self) -> ChatFiles {
+        //監視できていなければその場で走査する
+        if self.watcher.lock().unwrap().is_none() {
+            *self.files.write().unwrap() = ChatFiles::scan(&self.chat_dir);
+        }
+        self.files.read().unwrap().clone()
+    }
+}
+
+fn handler(
+    chat_dir: PathBuf,
+    files: Arc<RwLock<ChatFiles>>,
+) -> impl FnMut(notify::Result<Event>) + Send + 'static {
+    move |event| match event {
+        Ok(event) => {
+            let rescan = match event.kind {
+                EventKind::Create(_) | EventKind::Remove(_) => true,
+                EventKind::Modify(ModifyKind::Name(_)) => true,
+                //書き込み中のログ以外に書き込まれた
+                EventKind::Modify(_) => {
+                    let files = files.read().unwrap();
+                    let active = files.active.as_ref().map(Path::new);
+                    event
+                        .paths
+                        .iter()
+                        .any(|path| Some(path.as_path()) != active)
+                }
+                _ => false,
+            };
+            if rescan {
+                let scanned = ChatFiles::scan(&chat_dir);
+                #[cfg(debug_assertions)]
+                println!("chat files changed {:?}", scanned.active);
+                *files.write().unwrap() = scanned;
+            }
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+}
+
+fn watch(chat_dir: &Path, files: &Arc<RwLock<ChatFiles>>) -> Option<Box<dyn Watcher + Send>> {
+    let recommended = RecommendedWatcher::new(
+        handler(chat_dir.to_path_buf(), files.clone()),
+        Config::default(),
+    )
+    .and_then(|mut watcher| {
+        watcher.watch(chat_dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    match recommended {
+        Ok(watcher) => return Some(Box::new(watcher)),
+        Err(error) => eprintln!("{}", error),
+    }
+    let poll = PollWatcher::new(
+        handler(chat_dir.to_path_buf(), files.clone()),
+        Config::default().with_poll_interval(Duration::from_secs(2)),
+    )
+    .and_then(|mut watcher| {
+        watcher.watch(chat_dir, RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    match poll {
+        Ok(watcher) => Some(Box::new(watcher)),
+        Err(error) => {
+            eprintln!("{}", error);
+            None
+        }
+    }
+}
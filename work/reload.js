@@ -1,5 +1,69 @@
-function reload() {
-    location.reload(true);
+// ログが伸びたときだけサーバーから通知を受けてページを書き換える
+const source = new EventSource("/events");
+const counter = location.pathname.replace(/^\//, "");
+// 終わりのある期間や過去のログに絞り込んだ表は追記の影響を受けない
+const bounded = /[?&](to|session)=[^&]/.test(location.search);
+
+// 親画面のiframeの高さを合わせる
+function fitParent() {
+    if (window.parent !== window && window.parent.changeParentHeight) {
+        window.parent.changeParentHeight();
+    }
+}
+
+// 行は名前の降順に並んでいる
+function insertRow(table, name) {
+    let index = table.rows.length;
+    for (let i = 1; i < table.rows.length; i++) {
+        if (table.rows[i].dataset.name < name) {
+            index = i;
+            break;
+        }
+    }
+    const row = table.insertRow(index);
+    row.dataset.name = name;
+    row.insertCell().textContent = name;
+    row.insertCell().textContent = "0";
+    // 割合の列は次に取り直すまで空にしておく
+    while (row.cells.length < table.rows[0].cells.length) {
+        row.insertCell();
+    }
+    return row;
+}
+
+function applyDeltas(deltas) {
+    const table = document.getElementById("statics");
+    for (const [name, delta] of Object.entries(deltas)) {
+        let row = Array.from(table.rows).find(row => row.dataset.name === name);
+        if (!row) {
+            row = insertRow(table, name);
+        }
+        const cell = row.cells[1];
+        cell.textContent = Number(cell.textContent) + delta;
+    }
+    fitParent();
+}
+
+// 表の差分が送られないページは中身だけを取り直す
+function refresh() {
+    fetch(location.href)
+        .then(response => response.text())
+        .then(text => {
+            const page = new DOMParser().parseFromString(text, "text/html");
+            document.body.innerHTML = page.body.innerHTML;
+            fitParent();
+        });
 }
 
-setTimeout(reload, 3000);
\ No newline at end of file
+source.addEventListener("delta", event => {
+    const data = JSON.parse(event.data);
+    if (data.counter === counter && !bounded && document.getElementById("statics")) {
+        applyDeltas(data.deltas);
+    }
+});
+source.addEventListener("log", () => {
+    if (!bounded && !document.getElementById("statics")) {
+        refresh();
+    }
+});
+source.addEventListener("reload", () => location.reload());